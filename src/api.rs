@@ -0,0 +1,361 @@
+use futures::Future;
+use serde_json::{Map, Value};
+use tokio::sync::broadcast;
+
+use crate::{
+    method::PositionHow,
+    options::{GlobalOptions, TaskOptions},
+    response::{self, Notification},
+    Client, Error, TaskHooks,
+};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Methods of aria2 RPC implemented by [`Client`].
+///
+/// Code that depends on this trait instead of `Client` can be tested with a hand-written mock.
+///
+/// Methods return `impl Future`, so the trait is not object safe.
+/// Use it via generics, e.g. `async fn run(api: &impl Aria2Api)`.
+///
+/// See [`Client`] for the documentation of each method.
+pub trait Aria2Api {
+    fn get_version(&self) -> impl Future<Output = Result<response::Version>> + Send;
+
+    fn add_uri(
+        &self,
+        uris: Vec<String>,
+        options: Option<TaskOptions>,
+        position: Option<u32>,
+        hooks: Option<TaskHooks>,
+    ) -> impl Future<Output = Result<String>> + Send;
+
+    fn add_torrent(
+        &self,
+        torrent: Vec<u8>,
+        uris: Option<Vec<String>>,
+        options: Option<TaskOptions>,
+        position: Option<u32>,
+        hooks: Option<TaskHooks>,
+    ) -> impl Future<Output = Result<String>> + Send;
+
+    fn add_metalink(
+        &self,
+        metalink: Vec<u8>,
+        options: Option<TaskOptions>,
+        position: Option<u32>,
+        hooks: Option<TaskHooks>,
+    ) -> impl Future<Output = Result<String>> + Send;
+
+    fn remove(&self, gid: &str) -> impl Future<Output = Result<()>> + Send;
+
+    fn force_remove(&self, gid: &str) -> impl Future<Output = Result<()>> + Send;
+
+    fn pause(&self, gid: &str) -> impl Future<Output = Result<()>> + Send;
+
+    fn pause_all(&self) -> impl Future<Output = Result<()>> + Send;
+
+    fn force_pause(&self, gid: &str) -> impl Future<Output = Result<()>> + Send;
+
+    fn force_pause_all(&self) -> impl Future<Output = Result<()>> + Send;
+
+    fn unpause(&self, gid: &str) -> impl Future<Output = Result<()>> + Send;
+
+    fn unpause_all(&self) -> impl Future<Output = Result<()>> + Send;
+
+    fn custom_tell_status(
+        &self,
+        gid: &str,
+        keys: Option<Vec<String>>,
+    ) -> impl Future<Output = Result<Map<String, Value>>> + Send;
+
+    fn tell_status(&self, gid: &str) -> impl Future<Output = Result<response::Status>> + Send;
+
+    fn get_uris(&self, gid: &str) -> impl Future<Output = Result<Vec<response::Uri>>> + Send;
+
+    fn get_files(&self, gid: &str) -> impl Future<Output = Result<Vec<response::File>>> + Send;
+
+    fn get_peers(&self, gid: &str) -> impl Future<Output = Result<Vec<response::Peer>>> + Send;
+
+    fn get_servers(
+        &self,
+        gid: &str,
+    ) -> impl Future<Output = Result<Vec<response::GetServersResult>>> + Send;
+
+    fn tell_active(&self) -> impl Future<Output = Result<Vec<response::Status>>> + Send;
+
+    fn tell_waiting(
+        &self,
+        offset: i32,
+        num: i32,
+    ) -> impl Future<Output = Result<Vec<response::Status>>> + Send;
+
+    fn tell_stopped(
+        &self,
+        offset: i32,
+        num: i32,
+    ) -> impl Future<Output = Result<Vec<response::Status>>> + Send;
+
+    fn custom_tell_active(
+        &self,
+        keys: Option<Vec<String>>,
+    ) -> impl Future<Output = Result<Vec<Map<String, Value>>>> + Send;
+
+    fn custom_tell_waiting(
+        &self,
+        offset: i32,
+        num: i32,
+        keys: Option<Vec<String>>,
+    ) -> impl Future<Output = Result<Vec<Map<String, Value>>>> + Send;
+
+    fn custom_tell_stopped(
+        &self,
+        offset: i32,
+        num: i32,
+        keys: Option<Vec<String>>,
+    ) -> impl Future<Output = Result<Vec<Map<String, Value>>>> + Send;
+
+    fn change_position(
+        &self,
+        gid: &str,
+        pos: i32,
+        how: PositionHow,
+    ) -> impl Future<Output = Result<i32>> + Send;
+
+    fn change_uri(
+        &self,
+        gid: &str,
+        file_index: i32,
+        del_uris: Vec<String>,
+        add_uris: Vec<String>,
+        position: Option<i32>,
+    ) -> impl Future<Output = Result<(i32, i32)>> + Send;
+
+    fn get_option(&self, gid: &str) -> impl Future<Output = Result<TaskOptions>> + Send;
+
+    fn change_option(
+        &self,
+        gid: &str,
+        options: TaskOptions,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn get_global_option(&self) -> impl Future<Output = Result<GlobalOptions>> + Send;
+
+    fn change_global_option(
+        &self,
+        options: GlobalOptions,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn get_global_stat(&self) -> impl Future<Output = Result<response::GlobalStat>> + Send;
+
+    fn purge_download_result(&self) -> impl Future<Output = Result<()>> + Send;
+
+    fn remove_download_result(&self, gid: &str) -> impl Future<Output = Result<()>> + Send;
+
+    fn get_session_info(&self) -> impl Future<Output = Result<response::SessionInfo>> + Send;
+
+    fn shutdown(&self) -> impl Future<Output = Result<()>> + Send;
+
+    fn force_shutdown(&self) -> impl Future<Output = Result<()>> + Send;
+
+    fn save_session(&self) -> impl Future<Output = Result<()>> + Send;
+
+    fn subscribe_notifications(&self) -> broadcast::Receiver<Notification>;
+}
+
+impl Aria2Api for Client {
+    async fn get_version(&self) -> Result<response::Version> {
+        Client::get_version(self).await
+    }
+
+    async fn add_uri(
+        &self,
+        uris: Vec<String>,
+        options: Option<TaskOptions>,
+        position: Option<u32>,
+        hooks: Option<TaskHooks>,
+    ) -> Result<String> {
+        Client::add_uri(self, uris, options, position, hooks).await
+    }
+
+    async fn add_torrent(
+        &self,
+        torrent: Vec<u8>,
+        uris: Option<Vec<String>>,
+        options: Option<TaskOptions>,
+        position: Option<u32>,
+        hooks: Option<TaskHooks>,
+    ) -> Result<String> {
+        Client::add_torrent(self, torrent, uris, options, position, hooks).await
+    }
+
+    async fn add_metalink(
+        &self,
+        metalink: Vec<u8>,
+        options: Option<TaskOptions>,
+        position: Option<u32>,
+        hooks: Option<TaskHooks>,
+    ) -> Result<String> {
+        Client::add_metalink(self, metalink, options, position, hooks).await
+    }
+
+    async fn remove(&self, gid: &str) -> Result<()> {
+        Client::remove(self, gid).await
+    }
+
+    async fn force_remove(&self, gid: &str) -> Result<()> {
+        Client::force_remove(self, gid).await
+    }
+
+    async fn pause(&self, gid: &str) -> Result<()> {
+        Client::pause(self, gid).await
+    }
+
+    async fn pause_all(&self) -> Result<()> {
+        Client::pause_all(self).await
+    }
+
+    async fn force_pause(&self, gid: &str) -> Result<()> {
+        Client::force_pause(self, gid).await
+    }
+
+    async fn force_pause_all(&self) -> Result<()> {
+        Client::force_pause_all(self).await
+    }
+
+    async fn unpause(&self, gid: &str) -> Result<()> {
+        Client::unpause(self, gid).await
+    }
+
+    async fn unpause_all(&self) -> Result<()> {
+        Client::unpause_all(self).await
+    }
+
+    async fn custom_tell_status(
+        &self,
+        gid: &str,
+        keys: Option<Vec<String>>,
+    ) -> Result<Map<String, Value>> {
+        Client::custom_tell_status(self, gid, keys).await
+    }
+
+    async fn tell_status(&self, gid: &str) -> Result<response::Status> {
+        Client::tell_status(self, gid).await
+    }
+
+    async fn get_uris(&self, gid: &str) -> Result<Vec<response::Uri>> {
+        Client::get_uris(self, gid).await
+    }
+
+    async fn get_files(&self, gid: &str) -> Result<Vec<response::File>> {
+        Client::get_files(self, gid).await
+    }
+
+    async fn get_peers(&self, gid: &str) -> Result<Vec<response::Peer>> {
+        Client::get_peers(self, gid).await
+    }
+
+    async fn get_servers(&self, gid: &str) -> Result<Vec<response::GetServersResult>> {
+        Client::get_servers(self, gid).await
+    }
+
+    async fn tell_active(&self) -> Result<Vec<response::Status>> {
+        Client::tell_active(self).await
+    }
+
+    async fn tell_waiting(&self, offset: i32, num: i32) -> Result<Vec<response::Status>> {
+        Client::tell_waiting(self, offset, num).await
+    }
+
+    async fn tell_stopped(&self, offset: i32, num: i32) -> Result<Vec<response::Status>> {
+        Client::tell_stopped(self, offset, num).await
+    }
+
+    async fn custom_tell_active(
+        &self,
+        keys: Option<Vec<String>>,
+    ) -> Result<Vec<Map<String, Value>>> {
+        Client::custom_tell_active(self, keys).await
+    }
+
+    async fn custom_tell_waiting(
+        &self,
+        offset: i32,
+        num: i32,
+        keys: Option<Vec<String>>,
+    ) -> Result<Vec<Map<String, Value>>> {
+        Client::custom_tell_waiting(self, offset, num, keys).await
+    }
+
+    async fn custom_tell_stopped(
+        &self,
+        offset: i32,
+        num: i32,
+        keys: Option<Vec<String>>,
+    ) -> Result<Vec<Map<String, Value>>> {
+        Client::custom_tell_stopped(self, offset, num, keys).await
+    }
+
+    async fn change_position(&self, gid: &str, pos: i32, how: PositionHow) -> Result<i32> {
+        Client::change_position(self, gid, pos, how).await
+    }
+
+    async fn change_uri(
+        &self,
+        gid: &str,
+        file_index: i32,
+        del_uris: Vec<String>,
+        add_uris: Vec<String>,
+        position: Option<i32>,
+    ) -> Result<(i32, i32)> {
+        Client::change_uri(self, gid, file_index, del_uris, add_uris, position).await
+    }
+
+    async fn get_option(&self, gid: &str) -> Result<TaskOptions> {
+        Client::get_option(self, gid).await
+    }
+
+    async fn change_option(&self, gid: &str, options: TaskOptions) -> Result<()> {
+        Client::change_option(self, gid, options).await
+    }
+
+    async fn get_global_option(&self) -> Result<GlobalOptions> {
+        Client::get_global_option(self).await
+    }
+
+    async fn change_global_option(&self, options: GlobalOptions) -> Result<()> {
+        Client::change_global_option(self, options).await
+    }
+
+    async fn get_global_stat(&self) -> Result<response::GlobalStat> {
+        Client::get_global_stat(self).await
+    }
+
+    async fn purge_download_result(&self) -> Result<()> {
+        Client::purge_download_result(self).await
+    }
+
+    async fn remove_download_result(&self, gid: &str) -> Result<()> {
+        Client::remove_download_result(self, gid).await
+    }
+
+    async fn get_session_info(&self) -> Result<response::SessionInfo> {
+        Client::get_session_info(self).await
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        Client::shutdown(self).await
+    }
+
+    async fn force_shutdown(&self) -> Result<()> {
+        Client::force_shutdown(self).await
+    }
+
+    async fn save_session(&self) -> Result<()> {
+        Client::save_session(self).await
+    }
+
+    fn subscribe_notifications(&self) -> broadcast::Receiver<Notification> {
+        Client::subscribe_notifications(self)
+    }
+}
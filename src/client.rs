@@ -1,33 +1,259 @@
 use crate::{
-    error, response::Event, Client, Error, Hooks, InnerClient, RpcRequest, RpcResponse, TaskHooks,
-};
-use futures::{
-    future::BoxFuture,
-    prelude::*,
-    stream::{SplitSink, SplitStream},
-    StreamExt, TryStreamExt,
+    error, response::Event, utils, Aria2Error, Client, Error, Hooks, InnerClient, RpcRequest,
+    RpcResponse, Subscription, Subscriptions, TaskHooks,
 };
+use futures::{future::BoxFuture, prelude::*, StreamExt, TryStreamExt};
 use log::{debug, info};
-use serde::{de::DeserializeOwned, Deserialize};
+use rand::Rng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
 use snafu::prelude::*;
 use std::{
     collections::{hash_map::Entry, HashMap},
     sync::{
-        atomic::{AtomicU64, Ordering::SeqCst},
+        atomic::{AtomicBool, AtomicU64, Ordering::SeqCst},
         Arc, Mutex, Weak,
     },
     time::Duration,
 };
 use tokio::{
-    net::TcpStream,
+    io::{AsyncRead, AsyncWrite},
     select, spawn,
-    sync::{broadcast, mpsc, oneshot, Notify},
-    time::sleep,
+    sync::{broadcast, mpsc, oneshot, watch, Notify},
+    time::{sleep, timeout, Instant},
+};
+use tokio_tungstenite::{
+    tungstenite::{protocol::WebSocketConfig, Error as WsError, Message},
+    WebSocketStream,
 };
-use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
 
-use crate::response::Notification;
+use crate::response::{GlobalStat, Notification, SessionStats};
+
+/// Capacity of the notification broadcast channel.
+const NOTIFICATION_CAPACITY: usize = 16;
+
+/// The maximum number of tasks without hooks whose events are kept for `set_hooks`.
+/// Events of all of them are dropped once it's reached.
+const PENDING_EVENTS_CAPACITY: usize = 4096;
+
+/// How to generate the `id` of JSON-RPC requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdStrategy {
+    /// Incrementing integers starting from 0.
+    #[default]
+    Counter,
+    /// Random integers, so ids are unique across clients,
+    /// e.g. when multiple clients share a proxy that logs by id.
+    ///
+    /// Ids are less than 2^53 to be safe for JSON parsers using floats.
+    Random,
+}
+
+/// A method call sent in a batch by [`Client::multicall`].
+#[derive(Debug, Clone)]
+pub struct MethodCall {
+    /// Method name without the `aria2.` prefix, e.g. `tellStatus`.
+    pub method: String,
+    /// Params without the token, which is added by the client.
+    pub params: Vec<Value>,
+}
+
+impl MethodCall {
+    pub fn new(method: impl Into<String>, params: Vec<Value>) -> Self {
+        Self {
+            method: method.into(),
+            params,
+        }
+    }
+}
+
+/// Delays between attempts to connect again, see [`ClientConfig::reconnect`].
+///
+/// The delay starts from `initial_delay` and is multiplied by `multiplier` after each attempt,
+/// up to `max_delay`. Each delay is randomized between half and the full value,
+/// so many clients losing the connection at the same time don't reconnect all at once.
+/// It's reset once connected.
+#[derive(Debug, Clone)]
+pub struct ReconnectConfig {
+    /// Default: 1 second
+    pub initial_delay: Duration,
+
+    /// Default: 30 seconds
+    pub max_delay: Duration,
+
+    /// Default: 2.0
+    pub multiplier: f64,
+
+    /// The number of attempts since the last established connection before giving up.
+    /// `None` means retrying forever.
+    ///
+    /// Once given up, calls fail with `Error::Disconnected`.
+    ///
+    /// Default: `None`
+    pub max_retries: Option<u32>,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            max_retries: None,
+        }
+    }
+}
+
+/// State of the delays described by [`ReconnectConfig`].
+struct Backoff {
+    config: ReconnectConfig,
+    retries: u32,
+    delay: Duration,
+}
+
+impl Backoff {
+    fn new(config: ReconnectConfig) -> Self {
+        Self {
+            delay: config.initial_delay,
+            config,
+            retries: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.retries = 0;
+        self.delay = self.config.initial_delay;
+    }
+
+    /// The delay before the next attempt, or `None` if no more attempts should be made.
+    fn next_delay(&mut self) -> Option<Duration> {
+        if self
+            .config
+            .max_retries
+            .is_some_and(|max| self.retries >= max)
+        {
+            return None;
+        }
+        self.retries += 1;
+        let delay = self.delay;
+        self.delay = Duration::try_from_secs_f64(delay.as_secs_f64() * self.config.multiplier)
+            .unwrap_or(self.config.max_delay)
+            .min(self.config.max_delay)
+            .max(self.config.initial_delay);
+        Some(delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0)))
+    }
+}
+
+/// Configuration used by [`Client::connect_with_config`].
+#[derive(Debug, Clone)]
+pub struct ClientConfig {
+    /// The maximum size of a websocket message. `None` means no size limit.
+    ///
+    /// Responses larger than this limit make the connection close with an error,
+    /// e.g. `tell_active` or `tell_stopped` on an aria2 instance with thousands of downloads.
+    /// Raise it if the client keeps reconnecting on these calls.
+    ///
+    /// Default: 64 MiB
+    pub max_message_size: Option<usize>,
+
+    /// The maximum size of a single websocket frame payload. `None` means no size limit.
+    ///
+    /// Default: 16 MiB
+    pub max_frame_size: Option<usize>,
+
+    /// Default: `IdStrategy::Counter`
+    pub id_strategy: IdStrategy,
+
+    /// Query [`Client::server_info`] each time the connection is established,
+    /// so `Client::cached_server_info` is populated early.
+    ///
+    /// Default: false
+    pub fetch_server_info: bool,
+
+    /// Timeout of each connection attempt, including the TLS and websocket handshakes.
+    /// `None` means no timeout.
+    ///
+    /// `connect` returns before the connection is established,
+    /// so an attempt timing out is logged and retried like other failed attempts,
+    /// and calls made meanwhile fail with `Error::Timeout`.
+    /// Without it, an attempt to a host silently dropping packets may hang for minutes
+    /// before the next attempt.
+    ///
+    /// Default: 10 seconds
+    pub connect_timeout: Option<Duration>,
+
+    /// A name to tell the aria2 instance apart from others, see [`Client::label`].
+    ///
+    /// It's included in logs about the connection, like `aria2 (label): connect failed`.
+    ///
+    /// Default: `None`
+    pub label: Option<String>,
+
+    /// Send a websocket ping when nothing has been sent for this long,
+    /// so proxies and NATs don't close the idle connection.
+    /// Any message sent resets the timer, so it doesn't add traffic to a busy connection.
+    ///
+    /// Default: `None`, disabled
+    pub keepalive_interval: Option<Duration>,
+
+    /// With `keepalive_interval` set, the connection is considered lost
+    /// if nothing, including the pong, is received for `keepalive_interval` plus this long.
+    /// It's closed then, and connected again like other lost connections.
+    ///
+    /// Default: 20 seconds
+    pub keepalive_timeout: Duration,
+
+    /// Connect again when the connection is lost, e.g. when aria2 restarts.
+    ///
+    /// Calls pending at the moment fail with `Error::Disconnected` either way.
+    /// If disabled, the client stops once the first established connection is lost,
+    /// and later calls fail with `Error::Disconnected`.
+    /// Failed attempts before that are retried, and `Client::reconnect` still works until then.
+    ///
+    /// Hooks and notification receivers are kept across reconnects,
+    /// but notifications sent while disconnected are missed, see [`TaskHooks`].
+    ///
+    /// Default: true
+    pub auto_reconnect: bool,
+
+    /// Delays between attempts to connect, including ones before the first connection.
+    pub reconnect: ReconnectConfig,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            max_message_size: Some(64 << 20),
+            max_frame_size: Some(16 << 20),
+            id_strategy: IdStrategy::Counter,
+            fetch_server_info: false,
+            connect_timeout: Some(Duration::from_secs(10)),
+            label: None,
+            keepalive_interval: None,
+            keepalive_timeout: Duration::from_secs(20),
+            auto_reconnect: true,
+            reconnect: ReconnectConfig::default(),
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Prefix of logs about the connection.
+    fn log_name(&self) -> String {
+        match &self.label {
+            Some(label) => format!("aria2 ({})", label),
+            None => "aria2".to_string(),
+        }
+    }
+
+    fn websocket_config(&self) -> WebSocketConfig {
+        WebSocketConfig {
+            max_message_size: self.max_message_size,
+            max_frame_size: self.max_frame_size,
+            ..Default::default()
+        }
+    }
+}
 
 macro_rules! try_continue {
     ($res:expr) => {
@@ -54,24 +280,31 @@ where
 /// Checks all stopped tasks to see if some hooks need to be called.
 async fn on_reconnect(inner_client: Weak<InnerClient>) -> Result<(), Error> {
     // Response from `custom_tell_stopped` call
-    #[allow(non_snake_case)]
     #[derive(Debug, Clone, Deserialize)]
     struct TaskStatus {
         status: String,
-        totalLength: String,
-        completedLength: String,
         gid: String,
     }
 
     if let Some(client) = inner_client.upgrade() {
+        if client.hooks.lock().unwrap().0.is_empty() {
+            return Ok(());
+        }
+        // Only `num_stopped` results are kept by aria2.
+        let num = client
+            .call_and_subscribe::<GlobalStat>("getGlobalStat", vec![], None)
+            .await?
+            .num_stopped;
+        let num = utils::saturating_i32(num);
+
         let mut res: HashMap<String, TaskStatus> = HashMap::new();
         // Convert map to TaskStatus.
         for map in client
             .custom_tell_stopped(
                 0,
-                1000,
+                num,
                 Some(
-                    ["status", "totalLength", "completedLength", "gid"]
+                    ["status", "gid"]
                         .into_iter()
                         .map(|x| x.to_string())
                         .collect(),
@@ -89,53 +322,85 @@ async fn on_reconnect(inner_client: Weak<InnerClient>) -> Result<(), Error> {
         let mut lock = client.hooks.lock().unwrap();
         for (gid, hooks) in &mut lock.0 {
             if let Some(status) = res.get(gid) {
-                // Check if the task is finished by checking the length.
-                if status.totalLength == status.completedLength {
-                    if let Some(h) = hooks.on_complete.take() {
-                        spawn(h);
-                    }
-                } else if status.status == "error" {
-                    if let Some(h) = hooks.on_error.take() {
-                        spawn(h);
-                    }
+                match status.status.as_str() {
+                    "complete" => hooks.on_complete.drain(..).for_each(spawn_hook),
+                    "error" => hooks.on_error.drain(..).for_each(spawn_hook),
+                    "removed" => hooks.on_stop.drain(..).for_each(spawn_hook),
+                    _ => {}
                 }
             }
         }
+        // Hooks of stopped tasks will never be executed.
+        lock.0
+            .retain(|gid, hooks| hooks.is_some() && !res.contains_key(gid));
     }
     Ok(())
 }
 
-fn take_hook(event: Event, hook: &mut TaskHooks) -> Option<BoxFuture<'static, ()>> {
+/// Populate the server info cache in background.
+fn fetch_server_info(inner_client: Weak<InnerClient>) {
+    spawn(async move {
+        if let Some(client) = inner_client.upgrade() {
+            print_error(client.server_info().await.map(|_| ()));
+        }
+    });
+}
+
+fn spawn_hook(hook: BoxFuture<'static, ()>) {
+    spawn(hook);
+}
+
+fn take_hook(event: Event, hook: &mut TaskHooks) -> Vec<BoxFuture<'static, ()>> {
     use Event::*;
-    match event {
-        // "aria2.onDownloadStart" => &mut hook.on_start,
-        // "aria2.onDownloadPause" => &mut hook.on_pause,
-        // "aria2.onDownloadStop" => &mut hook.on_stop,
+    std::mem::take(match event {
+        Start => &mut hook.on_start,
+        Stop => &mut hook.on_stop,
         Complete => &mut hook.on_complete,
         Error => &mut hook.on_error,
-        BtComplete => &mut hook.on_complete,
-        _ => return None,
-    }
-    .take()
+        BtComplete => &mut hook.on_bt_complete,
+        _ => return Vec::new(),
+    })
+}
+
+/// Whether no more events are sent for the task after this one.
+fn is_final(event: Event) -> bool {
+    matches!(event, Event::Stop | Event::Complete | Event::Error)
 }
 
 fn process_nofitications(notification: &Notification, hooks: &Hooks) -> Result<(), Error> {
-    use Event::*;
-    if !matches!(notification.event, Complete | Error | BtComplete) {
+    if notification.event == Event::Pause {
         return Ok(());
     }
     let mut lock = hooks.lock().unwrap();
-    if let Some(hook) = lock.0.get_mut(&notification.gid) {
-        if let Some(hook) = take_hook(notification.event, hook) {
-            spawn(hook);
+    if let Some(hooks) = lock.0.get_mut(notification.gid.as_str()) {
+        take_hook(notification.event, hooks)
+            .into_iter()
+            .for_each(spawn_hook);
+        // Remaining hooks of a stopped task will never be executed.
+        if is_final(notification.event) || !hooks.is_some() {
+            lock.0.remove(notification.gid.as_str());
         }
     } else {
-        match lock.1.entry(notification.gid.clone()) {
+        // Kept in case hooks are set right after, e.g. when the task is still being added.
+        let pending_len = lock.1.len();
+        match lock.1.entry(notification.gid.to_string()) {
             Entry::Occupied(mut e) => {
-                e.get_mut().insert(notification.event);
+                let set = e.get_mut();
+                // Only the final event matters once the task has stopped.
+                if is_final(notification.event) {
+                    set.clear();
+                }
+                set.insert(notification.event);
             }
             Entry::Vacant(e) => {
-                e.insert([notification.event].into_iter().collect());
+                if pending_len >= PENDING_EVENTS_CAPACITY {
+                    debug!("aria2: too many tasks without hooks, dropping their events");
+                    lock.1.clear();
+                    lock.1
+                        .insert(notification.gid.to_string(), [notification.event].into());
+                } else {
+                    e.insert([notification.event].into_iter().collect());
+                }
             }
         }
     }
@@ -143,24 +408,31 @@ fn process_nofitications(notification: &Notification, hooks: &Hooks) -> Result<(
 }
 
 fn get_gid_from_notifictaion(req: &RpcRequest) -> Option<&str> {
-    req.params.get(0)?.get("gid")?.as_str()
+    req.params.first()?.get("gid")?.as_str()
 }
 
 impl InnerClient {
     fn id(&self) -> u64 {
-        self.id.fetch_add(1, SeqCst)
+        match self.id_strategy {
+            IdStrategy::Counter => self.id.fetch_add(1, SeqCst),
+            IdStrategy::Random => rand::random::<u64>() >> 11,
+        }
     }
 
     fn subscribe_id<T>(
         &self,
         id: u64,
+        gid: Option<String>,
         timeout: Option<Duration>,
     ) -> impl Future<Output = Result<T, Error>>
     where
         T: DeserializeOwned + Send,
     {
         let (tx, rx) = oneshot::channel();
-        self.subscriptions.lock().unwrap().insert(id, tx);
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .insert(id, Subscription { tx, gid });
         let timeout = timeout.unwrap_or(self.default_timeout);
         let subscriptions = self.subscriptions.clone();
 
@@ -177,9 +449,10 @@ impl InnerClient {
                     }
                 }
             };
+            let res = res?;
 
             if let Some(err) = res.error {
-                return Err(Error::Aria2 { source: err });
+                return Err(Error::from_aria2(err));
             }
 
             if let Some(v) = res.result {
@@ -190,49 +463,256 @@ impl InnerClient {
         }
     }
 
-    async fn call(&self, id: u64, method: &str, mut params: Vec<Value>) -> Result<(), Error> {
+    fn with_token(&self, mut params: Vec<Value>) -> Vec<Value> {
         if let Some(ref token) = self.token {
             params.insert(0, Value::String(token.clone()))
         }
+        params
+    }
+
+    async fn call(&self, id: u64, method: &str, params: Vec<Value>) -> Result<(), Error> {
+        self.send(id, "aria2.".to_string() + method, self.with_token(params))
+            .await
+    }
+
+    async fn send(&self, id: u64, method: String, params: Vec<Value>) -> Result<(), Error> {
         let req = RpcRequest {
             id: Some(id),
             jsonrpc: "2.0".to_string(),
-            method: "aria2.".to_string() + method,
+            method,
             params,
         };
+        self.send_request(&req).await
+    }
+
+    async fn send_request(&self, req: &impl Serialize) -> Result<(), Error> {
         self.tx_write
             .send(Message::Text(
-                serde_json::to_string(&req).context(error::JsonSnafu)?,
+                serde_json::to_string(req).context(error::JsonSnafu)?,
             ))
             .await
-            .context(error::MpscSendMessageSnafu)?;
+            // The background task has stopped and will not connect again.
+            .map_err(|_| Error::Disconnected)?;
         Ok(())
     }
 
+    /// Resolve pending calls operating on the task with `Error::Cancelled`,
+    /// and return the number of cancelled calls.
+    fn abort_pending_for(&self, gid: &str) -> usize {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let ids: Vec<u64> = subscriptions
+            .iter()
+            .filter(|(_, sub)| sub.gid.as_deref() == Some(gid))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &ids {
+            if let Some(sub) = subscriptions.remove(id) {
+                let _ = sub.tx.send(Err(Error::Cancelled));
+            }
+        }
+        ids.len()
+    }
+
     pub async fn call_and_subscribe<T>(
         &self,
         method: &str,
         params: Vec<Value>,
         timeout: Option<Duration>,
     ) -> Result<T, Error>
+    where
+        T: DeserializeOwned + Send,
+    {
+        self.call_with_gid(None, method, params, timeout).await
+    }
+
+    /// Same as `call_and_subscribe`, but the call is tagged with the gid of the task it operates on,
+    /// so it can be cancelled by `abort_pending_for`.
+    pub(crate) async fn call_with_gid<T>(
+        &self,
+        gid: Option<&str>,
+        method: &str,
+        params: Vec<Value>,
+        timeout: Option<Duration>,
+    ) -> Result<T, Error>
     where
         T: DeserializeOwned + Send,
     {
         let id = self.id();
-        let fut = self.subscribe_id::<T>(id, timeout);
+        let fut = self.subscribe_id::<T>(id, gid.map(str::to_string), timeout);
         // subscribe before calling
         self.call(id, method, params).await?;
         fut.await
     }
+
+    /// Call a `system.` method, which doesn't take the token.
+    pub(crate) async fn call_system<T>(&self, method: &str) -> Result<T, Error>
+    where
+        T: DeserializeOwned + Send,
+    {
+        let id = self.id();
+        let fut = self.subscribe_id::<T>(id, None, None);
+        self.send(id, "system.".to_string() + method, vec![])
+            .await?;
+        fut.await
+    }
+
+    /// Call multiple methods in a single request by `system.multicall`,
+    /// and return results in the same order.
+    ///
+    /// Methods are given without the `aria2.` prefix like `call_and_subscribe`.
+    ///
+    /// If aria2 doesn't support `system.multicall`, the methods are called one by one,
+    /// and it's not tried again until reconnected.
+    pub(crate) async fn multicall<M: AsRef<str>>(
+        &self,
+        calls: Vec<(M, Vec<Value>)>,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<Result<Value, Error>>, Error> {
+        if calls.is_empty() {
+            return Ok(Vec::new());
+        }
+        if !self.multicall_unsupported.load(SeqCst) {
+            // Params are borrowed, since they are needed again if `system.multicall` is unsupported.
+            #[derive(Serialize)]
+            #[serde(rename_all = "camelCase")]
+            struct Entry<'a> {
+                method_name: String,
+                params: Vec<&'a Value>,
+            }
+            #[derive(Serialize)]
+            struct Request<'a> {
+                id: u64,
+                jsonrpc: &'a str,
+                method: &'a str,
+                params: [Vec<Entry<'a>>; 1],
+            }
+
+            // Tokens are checked for each call instead of `system.multicall`.
+            let token = self.token.clone().map(Value::String);
+            let batch = calls
+                .iter()
+                .map(|(method, params)| Entry {
+                    method_name: "aria2.".to_string() + method.as_ref(),
+                    params: token.iter().chain(params).collect(),
+                })
+                .collect();
+            let id = self.id();
+            let fut = self.subscribe_id::<Vec<Value>>(id, None, timeout);
+            self.send_request(&Request {
+                id,
+                jsonrpc: "2.0",
+                method: "system.multicall",
+                params: [batch],
+            })
+            .await?;
+            match fut.await {
+                Ok(results) => return Ok(results.into_iter().map(multicall_result).collect()),
+                Err(Error::Aria2 { source }) if source.message.contains("No such method") => {
+                    info!(
+                        "{}: system.multicall is not supported, calling one by one",
+                        self.log_name
+                    );
+                    self.multicall_unsupported.store(true, SeqCst);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let mut results = Vec::with_capacity(calls.len());
+        for (method, params) in calls {
+            match self
+                .call_and_subscribe(method.as_ref(), params, timeout)
+                .await
+            {
+                Ok(v) => results.push(Ok(v)),
+                // Errors of the call itself are returned per call like `system.multicall`.
+                Err(err @ (Error::Aria2 { .. } | Error::GidNotFound { .. })) => {
+                    results.push(Err(err))
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(results)
+    }
 }
 
+/// Results of `system.multicall` are wrapped in single-item arrays,
+/// and errors are structs with `code` and `message`.
+fn multicall_result(v: Value) -> Result<Value, Error> {
+    match v {
+        Value::Array(mut a) if a.len() == 1 => Ok(a.remove(0)),
+        Value::Object(_) => match serde_json::from_value::<Aria2Error>(v.clone()) {
+            Ok(err) => Err(Error::from_aria2(err)),
+            Err(_) => error::UnexpectedMessageSnafu {
+                message: v.to_string(),
+            }
+            .fail(),
+        },
+        v => error::UnexpectedMessageSnafu {
+            message: v.to_string(),
+        }
+        .fail(),
+    }
+}
+
+/// Counters of [`SessionStats`], shared between the client and the worker.
+#[derive(Default)]
+pub(crate) struct SessionCounters {
+    started: AtomicU64,
+    completed: AtomicU64,
+    errored: AtomicU64,
+}
+
+impl SessionCounters {
+    fn record(&self, event: Event) {
+        let counter = match event {
+            Event::Start => &self.started,
+            Event::Complete => &self.completed,
+            Event::Error => &self.errored,
+            _ => return,
+        };
+        counter.fetch_add(1, SeqCst);
+    }
+
+    fn reset(&self) {
+        self.started.store(0, SeqCst);
+        self.completed.store(0, SeqCst);
+        self.errored.store(0, SeqCst);
+    }
+
+    fn load(&self) -> SessionStats {
+        SessionStats {
+            started: self.started.load(SeqCst),
+            completed: self.completed.load(SeqCst),
+            errored: self.errored.load(SeqCst),
+        }
+    }
+}
+
+/// Read messages from the websocket.
+///
+/// Fragmented messages are reassembled by tungstenite before being yielded,
+/// so each `Message::Text` is a complete JSON message.
+/// A message left incomplete by a closed connection is returned as an error and never parsed.
+/// Control and binary messages are skipped, and the loop stops on a close frame.
 async fn read_worker(
-    mut read: SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>,
-    subscriptions: Arc<Mutex<HashMap<u64, oneshot::Sender<RpcResponse>>>>,
+    mut read: impl Stream<Item = Result<Message, WsError>> + Unpin,
+    subscriptions: Subscriptions,
     hooks: Hooks,
     tx_not: broadcast::Sender<Notification>,
+    tx_raw_not: broadcast::Sender<RpcRequest>,
+    dropped_notifications: Arc<AtomicU64>,
+    session: Arc<SessionCounters>,
 ) -> Result<(), Error> {
-    while let Some(Message::Text(s)) = read.try_next().await.context(error::WebsocketSnafu)? {
+    while let Some(msg) = read.try_next().await.context(error::WebsocketSnafu)? {
+        let s = match msg {
+            Message::Text(s) => s,
+            // Pings are answered by tungstenite on the next read.
+            Message::Ping(_) | Message::Pong(_) | Message::Binary(_) | Message::Frame(_) => {
+                continue
+            }
+            Message::Close(_) => break,
+        };
         print_error((|| -> Result<(), Error> {
             let v: Value = serde_json::from_str(&s).context(error::JsonSnafu)?;
             if let Value::Object(obj) = &v {
@@ -241,11 +721,19 @@ async fn read_worker(
                     // https://aria2.github.io/manual/en/html/aria2c.html#notifications
                     let errf = || error::UnexpectedMessageSnafu { message: s.clone() };
                     let req: RpcRequest = serde_json::from_value(v).context(error::JsonSnafu)?;
+                    if tx_raw_not.receiver_count() > 0 {
+                        let _ = tx_raw_not.send(req.clone());
+                    }
                     let gid = get_gid_from_notifictaion(&req).with_context(errf)?;
                     let not = Notification::new(gid.to_string(), &req.method).with_context(errf)?;
 
+                    session.record(not.event);
                     process_nofitications(&not, &hooks)?;
 
+                    if tx_not.len() >= NOTIFICATION_CAPACITY {
+                        // The oldest notification will be dropped for the slowest receivers.
+                        dropped_notifications.fetch_add(1, SeqCst);
+                    }
                     let _ = tx_not.send(not);
                     return Ok(());
                 }
@@ -254,9 +742,9 @@ async fn read_worker(
             // The message can be a response.
             let res: RpcResponse = serde_json::from_value(v).context(error::JsonSnafu)?;
             if let Some(ref id) = res.id {
-                let tx = subscriptions.lock().unwrap().remove(id);
-                if let Some(tx) = tx {
-                    let _ = tx.send(res);
+                let sub = subscriptions.lock().unwrap().remove(id);
+                if let Some(sub) = sub {
+                    let _ = sub.tx.send(Ok(res));
                 }
             }
             Ok(())
@@ -265,12 +753,22 @@ async fn read_worker(
     Ok(())
 }
 
+/// Write messages to the websocket.
+///
+/// If `keepalive` is set, a ping is sent whenever nothing has been sent for that long.
 async fn write_worker(
-    mut write: SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>,
+    mut write: impl Sink<Message, Error = WsError> + Unpin,
     mut rx_write: mpsc::Receiver<Message>,
     exit: Arc<Notify>,
+    keepalive: Option<Duration>,
 ) -> mpsc::Receiver<Message> {
     loop {
+        let idle = async {
+            match keepalive {
+                Some(interval) => sleep(interval).await,
+                None => future::pending().await,
+            }
+        };
         select! {
             msg = rx_write.recv() => {
                 if let Some(msg) = msg {
@@ -280,6 +778,9 @@ async fn write_worker(
                     return rx_write;
                 }
             },
+            _ = idle => {
+                try_continue!(write.send(Message::Ping(Vec::new())).await);
+            },
             _ = exit.notified() => {
                 return rx_write;
             }
@@ -287,6 +788,125 @@ async fn write_worker(
     }
 }
 
+/// State owned by the background task driving the websocket connections of a client.
+struct Worker {
+    subscriptions: Subscriptions,
+    hooks: Hooks,
+    tx_not: broadcast::Sender<Notification>,
+    tx_raw_not: broadcast::Sender<RpcRequest>,
+    dropped_notifications: Arc<AtomicU64>,
+    session: Arc<SessionCounters>,
+    shutdown: Arc<Notify>,
+    reconnect: Arc<Notify>,
+    connected: watch::Sender<u64>,
+    // Taken by `write_worker` while connected, and given back on disconnect.
+    rx_write: Option<mpsc::Receiver<Message>>,
+    log_name: String,
+    keepalive: Option<Duration>,
+    keepalive_timeout: Duration,
+}
+
+/// Why [`Worker::serve`] returned.
+enum Disconnect {
+    Closed,
+    Reconnect,
+    Shutdown,
+}
+
+impl Worker {
+    /// Serve a single connection until it's disconnected.
+    async fn serve<S>(&mut self, ws: WebSocketStream<S>, on_connected: impl FnOnce()) -> Disconnect
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (write, read) = ws.split();
+        self.session.reset();
+        let last_read = Arc::new(Mutex::new(Instant::now()));
+        let read = read.inspect({
+            let last_read = last_read.clone();
+            move |_| *last_read.lock().unwrap() = Instant::now()
+        });
+        let read_fut = read_worker(
+            read,
+            self.subscriptions.clone(),
+            self.hooks.clone(),
+            self.tx_not.clone(),
+            self.tx_raw_not.clone(),
+            self.dropped_notifications.clone(),
+            self.session.clone(),
+        );
+        // `read_fut` will be dropped if current task is stopped.
+
+        let exit = Arc::new(Notify::new());
+        let rx_write = self.rx_write.take().expect("rx_write is taken");
+        let write_fut = spawn(write_worker(write, rx_write, exit.clone(), self.keepalive));
+
+        on_connected();
+        self.connected.send_modify(|n| *n += 1);
+
+        // Resolves if the pong to the keepalive ping is not received in time.
+        let stale = async {
+            let Some(interval) = self.keepalive else {
+                return future::pending().await;
+            };
+            let limit = interval + self.keepalive_timeout;
+            loop {
+                let elapsed = last_read.lock().unwrap().elapsed();
+                if elapsed >= limit {
+                    return;
+                }
+                sleep(limit - elapsed).await;
+            }
+        };
+
+        let disconnect = select! {
+            result = read_fut => {
+                debug!("{} disconnected: {:?}", self.log_name, result);
+                Disconnect::Closed
+            },
+            _ = stale => {
+                info!("{}: no response to keepalive ping, disconnecting", self.log_name);
+                Disconnect::Closed
+            },
+            _ = self.reconnect.notified() => {
+                debug!("{} reconnecting", self.log_name);
+                Disconnect::Reconnect
+            },
+            _ = self.shutdown.notified() => {
+                debug!("{} client is exiting", self.log_name);
+                exit.notify_one();
+                return Disconnect::Shutdown;
+            }
+        };
+        // notify write_worker to exit.
+        // A permit is stored in case it hasn't started waiting yet, e.g. closed right away.
+        exit.notify_one();
+        self.rx_write = Some(write_fut.await.unwrap());
+        // re-initialize rx_write for the next connection.
+
+        // Responses of pending calls will never be received.
+        for (_, sub) in self.subscriptions.lock().unwrap().drain() {
+            let _ = sub.tx.send(Err(Error::Disconnected));
+        }
+        disconnect
+    }
+
+    /// Wait before connecting again.
+    /// Returns early on manual reconnect,
+    /// or `false` if the client is shutting down or has run out of retries.
+    async fn retry(&self, backoff: &mut Backoff) -> bool {
+        let Some(duration) = backoff.next_delay() else {
+            info!("{}: giving up reconnecting", self.log_name);
+            return false;
+        };
+        select! {
+            _ = sleep(duration) => true,
+            _ = self.reconnect.notified() => true,
+            _ = self.shutdown.notified() => false,
+        }
+    }
+}
+
 impl Client {
     /// Create a new `Client` that connects to the given url.
     ///
@@ -309,92 +929,213 @@ impl Client {
     ///         )
     ///         .await
     ///         .unwrap();
-    ///     client.force_remove(gid).await.unwrap();
+    ///     client.force_remove(&gid).await.unwrap();
     /// }
     /// ```
     pub async fn connect(url: &str, token: Option<&str>) -> Result<Self, Error> {
-        let url = url.to_string();
-        let (tx_write, mut rx_write) = mpsc::channel::<Message>(4);
+        Self::connect_with_config(url, token, ClientConfig::default()).await
+    }
+
+    /// Create the client and the state of its background task.
+    fn new(token: Option<&str>, config: &ClientConfig, can_reconnect: bool) -> (Self, Worker) {
+        let (tx_write, rx_write) = mpsc::channel::<Message>(4);
         // channel for sending messages to the aria2 server.
-        let subscriptions: Arc<Mutex<HashMap<u64, oneshot::Sender<RpcResponse>>>> =
-            Arc::new(Mutex::new(HashMap::new()));
+        let subscriptions: Subscriptions = Arc::new(Mutex::new(HashMap::new()));
         // Used for storing subscriptions.
         // On receiving a message, subscription with the same id to the message id will be removed and processed.
         let hooks: Hooks = Arc::new(Mutex::new((HashMap::new(), HashMap::new())));
         // The first hashmap stores the hooks, and the second hashmap stores the penging events for extra hooks run check.
         let shutdown = Arc::new(Notify::new());
         // sync all spawned tasks to shutdown
-        let (tx_not, _) = broadcast::channel(16);
+        let reconnect = Arc::new(Notify::new());
+        let (tx_connected, rx_connected) = watch::channel(0);
+        let (tx_not, _) = broadcast::channel(NOTIFICATION_CAPACITY);
         // Broadcast notifications to all subscribers.
         // The receiver is dropped cause no one subscribes for now.
         // The notifications can be received again by calling tx_not.subscribe().
+        let (tx_raw_not, _) = broadcast::channel(NOTIFICATION_CAPACITY);
+        let dropped_notifications = Arc::new(AtomicU64::new(0));
+        let session = Arc::new(SessionCounters::default());
 
         let inner = Arc::new(InnerClient {
             tx_write,
             id: AtomicU64::new(0),
+            id_strategy: config.id_strategy,
+            label: config.label.clone(),
+            log_name: config.log_name(),
             token: token.map(|t| "token:".to_string() + t),
             subscriptions: subscriptions.clone(),
             shutdown: shutdown.clone(),
+            reconnect: can_reconnect.then(|| reconnect.clone()),
+            connected: rx_connected,
             hooks: hooks.clone(),
             default_timeout: Duration::from_secs(10),
             extended_timeout: Duration::from_secs(120),
             tx_not: tx_not.clone(),
+            tx_raw_not: tx_raw_not.clone(),
+            dropped_notifications: dropped_notifications.clone(),
+            session: session.clone(),
+            multicall_unsupported: AtomicBool::new(false),
+            version: Mutex::new(None),
+            default_options: Mutex::new(None),
+            default_trackers: Mutex::new(Vec::new()),
+            server_info: Mutex::new(None),
         });
+        let worker = Worker {
+            subscriptions,
+            hooks,
+            tx_not,
+            tx_raw_not,
+            dropped_notifications,
+            session,
+            shutdown,
+            reconnect,
+            connected: tx_connected,
+            rx_write: Some(rx_write),
+            log_name: config.log_name(),
+            keepalive: config.keepalive_interval,
+            keepalive_timeout: config.keepalive_timeout,
+        };
+        (Self(inner), worker)
+    }
 
-        let inner_weak = Arc::downgrade(&inner);
+    /// Create a new `Client` that connects to the given url with the given config.
+    pub async fn connect_with_config(
+        url: &str,
+        token: Option<&str>,
+        config: ClientConfig,
+    ) -> Result<Self, Error> {
+        let url = url.to_string();
+        let ws_config = config.websocket_config();
+        let eager_server_info = config.fetch_server_info;
+        let connect_timeout = config.connect_timeout;
+        let auto_reconnect = config.auto_reconnect;
+        let mut backoff = Backoff::new(config.reconnect.clone());
+        let (client, mut worker) = Self::new(token, &config, true);
+
+        let inner_weak = Arc::downgrade(&client.0);
         // The following spawned task following will only hold a weak reference to the inner client.
         spawn(async move {
             let mut reconnect = false;
             loop {
-                let connected = select! {
-                    r = tokio_tungstenite::connect_async(&url) => r,
-                    _ = shutdown.notified() => return,
+                let attempt = async {
+                    let fut = tokio_tungstenite::connect_async_with_config(&url, Some(ws_config));
+                    match connect_timeout {
+                        Some(t) => timeout(t, fut)
+                            .await
+                            .context(error::TimeoutSnafu)?
+                            .context(error::WebsocketSnafu),
+                        None => fut.await.context(error::WebsocketSnafu),
+                    }
+                };
+                tokio::pin!(attempt);
+                let connected = loop {
+                    select! {
+                        r = &mut attempt => break r,
+                        // Already connecting, so keep the attempt instead of starting over.
+                        _ = worker.reconnect.notified() => {},
+                        _ = worker.shutdown.notified() => return,
+                    }
                 };
                 match connected {
                     Ok((ws, _)) => {
-                        let (write, read) = ws.split();
-                        let read_fut =
-                            read_worker(read, subscriptions.clone(), hooks.clone(), tx_not.clone());
-                        // `read_fut` will be dropped if current task is stopped.
-
-                        let exit = Arc::new(Notify::new());
-                        let write_fut = spawn(write_worker(write, rx_write, exit.clone()));
-
-                        if reconnect {
-                            spawn(on_reconnect(inner_weak.clone()));
-                        } else {
-                            reconnect = true;
-                            // run `on_reconnect` task next time.
-                        }
-
-                        select! {
-                            result = read_fut => {
-                                debug!("aria2 disconnected: {:?}", result);
-                                exit.notify_waiters();
-                                // notify write_worker to exit.
-                                rx_write = write_fut.await.unwrap();
-                                // re-initialize rx_write for the next connection.
-                                sleep(Duration::from_secs(1)).await;
-                            },
-                            _ = shutdown.notified() => {
-                                debug!("aria2 client is exiting");
-                                exit.notify_waiters();
-                                return;
+                        backoff.reset();
+                        let on_connected = || {
+                            if reconnect {
+                                if let Some(inner) = inner_weak.upgrade() {
+                                    *inner.version.lock().unwrap() = None;
+                                    *inner.server_info.lock().unwrap() = None;
+                                    inner.multicall_unsupported.store(false, SeqCst);
+                                    // Events of the last connection are not checked against new hooks.
+                                    inner.hooks.lock().unwrap().1.clear();
+                                }
+                                spawn(on_reconnect(inner_weak.clone()));
+                            } else {
+                                reconnect = true;
+                                // run `on_reconnect` task next time.
+                            }
+                            if eager_server_info {
+                                fetch_server_info(inner_weak.clone());
+                            }
+                        };
+                        match worker.serve(ws, on_connected).await {
+                            Disconnect::Shutdown => return,
+                            Disconnect::Reconnect => {}
+                            Disconnect::Closed => {
+                                if !auto_reconnect {
+                                    info!("{}: disconnected, not reconnecting", worker.log_name);
+                                    return;
+                                }
+                                if !worker.retry(&mut backoff).await {
+                                    return;
+                                }
                             }
                         }
                     }
                     Err(err) => {
-                        info!("aria2: connect failed: {}", err);
-                        sleep(Duration::from_secs(3)).await;
+                        info!("{}: connect failed: {}", worker.log_name, err);
+                        if !worker.retry(&mut backoff).await {
+                            return;
+                        }
                     }
                 }
             }
         });
 
-        Ok(Self(inner))
+        Ok(client)
     }
 
-    // Call aria2 without waiting for the response.
+    /// Create a new `Client` over an already established websocket connection,
+    /// e.g. one opened through a proxy or with custom TLS settings.
+    ///
+    /// The connection is not re-established once it's closed,
+    /// and later calls fail with `Error::Disconnected`, as does `Client::reconnect`.
+    /// Size limits of `config` are not applied, since `ws` is already configured.
+    ///
+    /// Must be called in a tokio runtime.
+    pub fn from_stream<S>(ws: WebSocketStream<S>, token: Option<&str>, config: ClientConfig) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (client, mut worker) = Self::new(token, &config, false);
+        let inner_weak = Arc::downgrade(&client.0);
+        spawn(async move {
+            worker
+                .serve(ws, || {
+                    if config.fetch_server_info {
+                        fetch_server_info(inner_weak);
+                    }
+                })
+                .await;
+        });
+        client
+    }
+
+    /// Close the current connection and connect again,
+    /// and wait for the new connection to be established in the default timeout.
+    ///
+    /// Hooks and notification receivers are kept.
+    /// Pending calls fail with `Error::Disconnected`, like when the connection is lost.
+    ///
+    /// Returns `Error::Disconnected` for clients created by `from_stream`.
+    pub async fn reconnect(&self) -> Result<(), Error> {
+        let reconnect = self
+            .0
+            .reconnect
+            .as_ref()
+            .context(error::DisconnectedSnafu)?;
+        let mut connected = self.0.connected.clone();
+        connected.borrow_and_update();
+        reconnect.notify_one();
+        tokio::time::timeout(self.0.default_timeout, connected.changed())
+            .await
+            .context(error::TimeoutSnafu)?
+            .map_err(|_| Error::Disconnected)
+    }
+
+    /// Call aria2 without waiting for the response.
+    ///
+    /// The method and params are the same as [`Client::call_and_subscribe`].
     pub async fn call(&self, id: u64, method: &str, params: Vec<Value>) -> Result<(), Error> {
         self.0.call(id, method, params).await
     }
@@ -402,9 +1143,27 @@ impl Client {
     /// Call aria2 with the given method and params,
     /// and return the result in timeout.
     ///
+    /// Use it for methods without a wrapper in this crate.
+    /// The method is given without the `aria2.` prefix, e.g. `getUris`.
+    /// The token is added as the first param by the client, so `params` must not include it.
+    ///
     /// If the timeout is `None`, the default timeout will be used.
     ///
     /// If the timeout is zero, there will be no timeout.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aria2_ws::{response::Uri, Client};
+    /// use serde_json::json;
+    ///
+    /// # async fn example(client: Client) -> Result<(), aria2_ws::Error> {
+    /// let uris: Vec<Uri> = client
+    ///     .call_and_subscribe("getUris", vec![json!("2089b05ecca3d829")], None)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
     pub async fn call_and_subscribe<T: DeserializeOwned + Send>(
         &self,
         method: &str,
@@ -414,6 +1173,68 @@ impl Client {
         self.0.call_and_subscribe(method, params, timeout).await
     }
 
+    /// Same as `call_and_subscribe`, for methods operating on the task with given gid.
+    pub(crate) async fn call_for_gid<T: DeserializeOwned + Send>(
+        &self,
+        gid: &str,
+        method: &str,
+        params: Vec<Value>,
+        timeout: Option<Duration>,
+    ) -> Result<T, Error> {
+        self.0
+            .call_with_gid(Some(gid), method, params, timeout)
+            .await
+    }
+
+    /// Call multiple methods in a single `system.multicall` request,
+    /// and return results in the same order.
+    ///
+    /// Each call fails independently, so errors responded by aria2 are returned per call,
+    /// while errors of the whole request, e.g. timeouts, are returned as the outer error.
+    ///
+    /// If aria2 doesn't support `system.multicall`, the methods are called one by one.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use aria2_ws::{Client, MethodCall};
+    /// use serde_json::json;
+    ///
+    /// # async fn example(client: Client, gids: Vec<String>) -> Result<(), aria2_ws::Error> {
+    /// let calls = gids
+    ///     .iter()
+    ///     .map(|gid| MethodCall::new("tellStatus", vec![json!(gid)]))
+    ///     .collect();
+    /// for result in client.multicall(calls, None).await? {
+    ///     println!("{:?}", result);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn multicall(
+        &self,
+        calls: Vec<MethodCall>,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<Result<Value, Error>>, Error> {
+        let calls = calls
+            .into_iter()
+            .map(|call| (call.method, call.params))
+            .collect();
+        self.0.multicall(calls, timeout).await
+    }
+
+    /// Make pending calls operating on the task with given gid fail with `Error::Cancelled`,
+    /// and return the number of cancelled calls.
+    ///
+    /// This only stops waiting for the responses, and aria2 may still process the requests.
+    /// Calls of other tasks are not affected.
+    ///
+    /// Only calls of methods taking a gid, like `tell_status` or `remove`, are matched.
+    /// Calls made by `call_and_subscribe` are not.
+    pub fn abort_pending_for(&self, gid: &str) -> usize {
+        self.0.abort_pending_for(gid)
+    }
+
     /// Set hook for task with given gid.
     pub async fn set_hooks(&self, gid: &str, hooks: Option<TaskHooks>) {
         if let Some(mut hooks) = hooks {
@@ -421,20 +1242,699 @@ impl Client {
                 return;
             }
             let mut lock = self.0.hooks.lock().unwrap();
+            let mut stopped = false;
             if let Some(set) = lock.1.remove(gid) {
                 for event in set {
-                    if let Some(h) = take_hook(event, &mut hooks) {
-                        spawn(h);
-                    }
+                    take_hook(event, &mut hooks)
+                        .into_iter()
+                        .for_each(spawn_hook);
+                    stopped |= is_final(event);
                 }
             }
-            if hooks.is_some() {
+            if hooks.is_some() && !stopped {
                 lock.0.insert(gid.to_string(), hooks);
             }
         }
     }
 
+    /// Attach hooks to an existing task, e.g. one found by `tell_active` after a restart.
+    ///
+    /// Hooks replace the ones previously attached to the task.
+    /// If the task has completed or errored since the client last (re)connected,
+    /// the matching hook runs immediately.
+    ///
+    /// Hooks of tasks that stopped before that will never run,
+    /// so check the status of the task with `tell_status` after attaching them.
+    pub async fn attach_hooks(&self, gid: &str, hooks: TaskHooks) {
+        self.set_hooks(gid, Some(hooks)).await
+    }
+
+    /// The label given by [`ClientConfig::label`].
+    pub fn label(&self) -> Option<&str> {
+        self.0.label.as_deref()
+    }
+
+    /// Subscribe to notifications from aria2.
+    ///
+    /// The broadcast channel is created before `connect` returns,
+    /// and messages are only read after the websocket is connected,
+    /// so a receiver taken right after `connect` will not miss any notification.
+    ///
+    /// Dropping the receiver unsubscribes it. Sending never waits on receivers,
+    /// and notifications arriving while nobody is subscribed are discarded
+    /// instead of buffered, so a later receiver only gets new ones.
     pub fn subscribe_notifications(&self) -> broadcast::Receiver<Notification> {
         self.0.tx_not.subscribe()
     }
+
+    /// Subscribe to notifications as received from aria2, including unknown ones.
+    ///
+    /// Use it for notifications not covered by [`Event`], e.g. sent by newer aria2 versions
+    /// or aria2-compatible servers, and [`Client::subscribe_notifications`] otherwise.
+    /// Malformed notifications are sent here too, as long as they are valid requests.
+    pub fn subscribe_raw_notifications(&self) -> broadcast::Receiver<RpcRequest> {
+        self.0.tx_raw_not.subscribe()
+    }
+
+    /// The number of notifications dropped because some receivers couldn't keep up.
+    ///
+    /// The counter increases when a notification is sent while the channel is full,
+    /// in which case the slowest receivers will get `RecvError::Lagged`.
+    pub fn dropped_notification_count(&self) -> u64 {
+        self.0.dropped_notifications.load(SeqCst)
+    }
+
+    /// Numbers of downloads started, completed and errored,
+    /// counted from notifications since the client connected.
+    ///
+    /// aria2 has no cumulative counters, so the numbers are reset on every reconnect,
+    /// and notifications missed while disconnected are not counted.
+    pub fn session_stats(&self) -> SessionStats {
+        self.0.session.load()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{atomic::AtomicU64, Arc, Mutex},
+        time::Duration,
+    };
+
+    use futures::{SinkExt, StreamExt};
+    use serde_json::{json, Value};
+    use tokio::{
+        io::{AsyncWriteExt, DuplexStream},
+        sync::broadcast,
+    };
+    use tokio_tungstenite::{
+        tungstenite::{protocol::Role, Message},
+        WebSocketStream,
+    };
+
+    use super::{
+        multicall_result, process_nofitications, read_worker, Backoff, NOTIFICATION_CAPACITY,
+    };
+    use crate::{
+        response::{Event, Notification, SessionStats},
+        utils::encode_base64,
+        Client, ClientConfig, Error, Hooks, ReconnectConfig, TaskHooks,
+    };
+
+    const NOTIFICATION: &str = r#"{"jsonrpc":"2.0","method":"aria2.onDownloadStart","params":[{"gid":"2089b05ecca3d829"}]}"#;
+
+    /// Write an unmasked frame from the server side of the mock transport.
+    async fn write_frame(server: &mut DuplexStream, fin: bool, opcode: u8, payload: &[u8]) {
+        assert!(payload.len() < 126);
+        let head = [(fin as u8) << 7 | opcode, payload.len() as u8];
+        server.write_all(&head).await.unwrap();
+        server.write_all(payload).await.unwrap();
+    }
+
+    /// Run `read_worker` over a mock transport, and return received notifications.
+    async fn read_frames(frames: &[(bool, u8, &[u8])]) -> Vec<Notification> {
+        let (client, mut server) = tokio::io::duplex(4096);
+        let ws = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        let (_write, read) = ws.split();
+        let hooks: Hooks = Arc::new(Mutex::new((HashMap::new(), HashMap::new())));
+        let (tx_not, mut rx_not) = broadcast::channel(NOTIFICATION_CAPACITY);
+
+        for (fin, opcode, payload) in frames {
+            write_frame(&mut server, *fin, *opcode, payload).await;
+        }
+        drop(server);
+
+        let _ = read_worker(
+            read,
+            Arc::new(Mutex::new(HashMap::new())),
+            hooks,
+            tx_not,
+            broadcast::channel(1).0,
+            Arc::new(AtomicU64::new(0)),
+            Default::default(),
+        )
+        .await;
+
+        let mut nots = Vec::new();
+        while let Ok(not) = rx_not.try_recv() {
+            nots.push(not);
+        }
+        nots
+    }
+
+    #[tokio::test]
+    async fn fragmented_message() {
+        let (a, b) = NOTIFICATION.as_bytes().split_at(30);
+        let nots = read_frames(&[(false, 0x1, a), (true, 0x0, b)]).await;
+        assert_eq!(nots.len(), 1);
+        assert_eq!(nots[0].gid, "2089b05ecca3d829");
+        assert_eq!(nots[0].event, Event::Start);
+    }
+
+    #[tokio::test]
+    async fn malformed_notification() {
+        let frames: [&[u8]; 4] = [
+            br#"{"jsonrpc":"2.0","method":"aria2.onDownloadStart","params":null}"#,
+            br#"{"jsonrpc":"2.0","method":"aria2.onDownloadStart","params":[null]}"#,
+            br#"{"jsonrpc":"2.0","method":"aria2.onDownloadStart"}"#,
+            NOTIFICATION.as_bytes(),
+        ];
+        let frames: Vec<_> = frames.iter().map(|f| (true, 0x1, *f)).collect();
+        // Malformed notifications are skipped, and the loop keeps running.
+        let nots = read_frames(&frames).await;
+        assert_eq!(nots.len(), 1);
+        assert_eq!(nots[0].gid, "2089b05ecca3d829");
+    }
+
+    #[tokio::test]
+    async fn incomplete_message() {
+        let (a, _) = NOTIFICATION.as_bytes().split_at(30);
+        let nots = read_frames(&[(false, 0x1, a)]).await;
+        assert!(nots.is_empty());
+    }
+
+    #[tokio::test]
+    async fn from_stream() {
+        let (client, server) = tokio::io::duplex(4096);
+        let ws = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        let client = Client::from_stream(ws, Some("secret"), ClientConfig::default());
+
+        tokio::spawn(async move {
+            while let Some(Ok(Message::Text(s))) = server.next().await {
+                let req: Value = serde_json::from_str(&s).unwrap();
+                assert_eq!(req["method"], "aria2.getVersion");
+                assert_eq!(req["params"][0], "token:secret");
+                let res = json!({
+                    "id": req["id"],
+                    "jsonrpc": "2.0",
+                    "result": {"version": "1.36.0", "enabledFeatures": []},
+                });
+                server.send(Message::Text(res.to_string())).await.unwrap();
+            }
+        });
+
+        assert_eq!(client.get_version().await.unwrap().version, "1.36.0");
+    }
+
+    #[test]
+    fn multicall_results() {
+        let results: Vec<_> = [
+            json!(["OK"]),
+            json!({"code": 1, "message": "GID 2089b05ecca3d829 is not found"}),
+            json!("OK"),
+            json!({"faultCode": 1, "faultString": "No URI to download."}),
+        ]
+        .into_iter()
+        .map(multicall_result)
+        .collect();
+        assert_eq!(results[0].as_ref().unwrap(), "OK");
+        assert!(matches!(results[1], Err(Error::GidNotFound { .. })));
+        assert!(matches!(results[2], Err(Error::UnexpectedMessage { .. })));
+        assert!(matches!(results[3], Err(Error::Aria2 { .. })));
+    }
+
+    #[tokio::test]
+    async fn abort_pending_for() {
+        let (client, server) = tokio::io::duplex(4096);
+        let ws = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        // The server never responds.
+        let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        tokio::spawn(async move { while server.next().await.is_some() {} });
+        let client = Client::from_stream(ws, None, ClientConfig::default());
+
+        let gid = "2089b05ecca3d829";
+        let pending = tokio::spawn({
+            let client = client.clone();
+            async move { client.tell_status(gid).await }
+        });
+        let other = tokio::spawn({
+            let client = client.clone();
+            async move { client.tell_status("0000000000000001").await }
+        });
+        while client.abort_pending_for(gid) == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert!(matches!(pending.await.unwrap(), Err(Error::Cancelled)));
+        // The other call is still pending.
+        assert!(!other.is_finished());
+        while client.abort_pending_for("0000000000000001") == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert!(matches!(other.await.unwrap(), Err(Error::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn payload_not_tagged_as_gid() {
+        let (client, server) = tokio::io::duplex(4096);
+        let ws = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        // The server never responds.
+        let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        tokio::spawn(async move { while server.next().await.is_some() {} });
+        let client = Client::from_stream(ws, None, ClientConfig::default());
+
+        let data: &[u8] = b"d4:infod6:lengthi1e4:name1:aee";
+        let pending = tokio::spawn({
+            let client = client.clone();
+            async move { client.add_torrent(data, None, None, None, None).await }
+        });
+        while client.0.subscriptions.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        assert!(client
+            .0
+            .subscriptions
+            .lock()
+            .unwrap()
+            .values()
+            .all(|sub| sub.gid.is_none()));
+        assert_eq!(client.abort_pending_for(&encode_base64(data)), 0);
+        assert!(!pending.is_finished());
+    }
+
+    #[tokio::test]
+    async fn disconnected() {
+        let (client, server) = tokio::io::duplex(4096);
+        let ws = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        // Close the connection on the first request.
+        tokio::spawn(async move {
+            server.next().await;
+        });
+        let client = Client::from_stream(ws, None, ClientConfig::default());
+
+        assert!(matches!(
+            client.get_version().await,
+            Err(Error::Disconnected)
+        ));
+        assert!(matches!(client.reconnect().await, Err(Error::Disconnected)));
+    }
+
+    #[tokio::test]
+    async fn resubscribe_notifications() {
+        let (client, server) = tokio::io::duplex(1 << 16);
+        let ws = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        let client = Client::from_stream(ws, None, ClientConfig::default());
+        drop(client.subscribe_notifications());
+
+        tokio::spawn(async move {
+            // Nobody is subscribed to these.
+            for _ in 0..NOTIFICATION_CAPACITY * 4 {
+                server
+                    .send(Message::Text(NOTIFICATION.to_string()))
+                    .await
+                    .unwrap();
+            }
+            for gid in ["0000000000000001", "0000000000000002"] {
+                let Some(Ok(Message::Text(s))) = server.next().await else {
+                    return;
+                };
+                let req: Value = serde_json::from_str(&s).unwrap();
+                let not = json!({
+                    "jsonrpc": "2.0",
+                    "method": "aria2.onDownloadComplete",
+                    "params": [{"gid": gid}],
+                });
+                server.send(Message::Text(not.to_string())).await.unwrap();
+                let res = json!({"id": req["id"], "jsonrpc": "2.0", "result": "OK"});
+                server.send(Message::Text(res.to_string())).await.unwrap();
+            }
+        });
+
+        // Notifications before the response are processed once it's received.
+        client.purge_download_result().await.unwrap();
+        let mut rx = client.subscribe_notifications();
+        client.purge_download_result().await.unwrap();
+
+        let not = rx.recv().await.unwrap();
+        assert_eq!(not.gid, "0000000000000002");
+        assert_eq!(not.event, Event::Complete);
+        assert!(rx.is_empty());
+        // Notifications without receivers are not buffered, so none is dropped.
+        assert_eq!(client.dropped_notification_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn multiple_hooks() {
+        let hooks: Hooks = Arc::new(Mutex::new((HashMap::new(), HashMap::new())));
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let task_hooks = (0..2).fold(TaskHooks::default(), |hooks, i| {
+            let tx = tx.clone();
+            hooks.with_on_complete(async move { tx.send(i).unwrap() })
+        });
+        hooks
+            .lock()
+            .unwrap()
+            .0
+            .insert("2089b05ecca3d829".to_string(), task_hooks);
+
+        let not = Notification::new("2089b05ecca3d829", "aria2.onDownloadComplete").unwrap();
+        process_nofitications(&not, &hooks).unwrap();
+        drop(tx);
+
+        let mut called = Vec::new();
+        while let Some(i) = rx.recv().await {
+            called.push(i);
+        }
+        called.sort();
+        assert_eq!(called, [0, 1]);
+        assert!(hooks.lock().unwrap().0.is_empty());
+    }
+
+    #[tokio::test]
+    async fn start_and_stop_hooks() {
+        let hooks: Hooks = Arc::new(Mutex::new((HashMap::new(), HashMap::new())));
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let task_hooks = TaskHooks::default()
+            .with_on_start({
+                let tx = tx.clone();
+                async move { tx.send("start").unwrap() }
+            })
+            .with_on_stop({
+                let tx = tx.clone();
+                async move { tx.send("stop").unwrap() }
+            })
+            .with_on_complete({
+                let tx = tx.clone();
+                async move { tx.send("complete").unwrap() }
+            });
+        drop(tx);
+        hooks
+            .lock()
+            .unwrap()
+            .0
+            .insert("2089b05ecca3d829".to_string(), task_hooks);
+
+        for method in ["aria2.onDownloadStart", "aria2.onDownloadStop"] {
+            let not = Notification::new("2089b05ecca3d829", method).unwrap();
+            process_nofitications(&not, &hooks).unwrap();
+        }
+        assert_eq!(rx.recv().await, Some("start"));
+        assert_eq!(rx.recv().await, Some("stop"));
+        // The hooks are dropped with `on_complete` once the task is stopped.
+        assert_eq!(rx.recv().await, None);
+        assert!(hooks.lock().unwrap().0.is_empty());
+
+        // Events of tasks without hooks are recorded, and only the final one is kept.
+        for method in ["aria2.onDownloadStart", "aria2.onDownloadStop"] {
+            let not = Notification::new("2089b05ecca3d829", method).unwrap();
+            process_nofitications(&not, &hooks).unwrap();
+        }
+        let lock = hooks.lock().unwrap();
+        assert!(lock.0.is_empty());
+        assert_eq!(lock.1.len(), 1);
+        assert_eq!(
+            lock.1["2089b05ecca3d829"],
+            [Event::Stop].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn backoff() {
+        let mut backoff = Backoff::new(ReconnectConfig {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5),
+            multiplier: 2.0,
+            max_retries: Some(4),
+        });
+        for _ in 0..2 {
+            let delays: Vec<_> = std::iter::from_fn(|| backoff.next_delay()).collect();
+            let expected = [1, 2, 4, 5].map(Duration::from_secs);
+            assert_eq!(delays.len(), expected.len());
+            for (delay, max) in delays.into_iter().zip(expected) {
+                // Randomized between half and the full delay.
+                assert!(delay <= max && delay >= max / 2, "{:?} {:?}", delay, max);
+            }
+            backoff.reset();
+        }
+    }
+
+    #[tokio::test]
+    async fn no_auto_reconnect() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let config = ClientConfig {
+            auto_reconnect: false,
+            ..Default::default()
+        };
+        let client = Client::connect_with_config(&url, None, config)
+            .await
+            .unwrap();
+
+        // Close the first connection right after the handshake.
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        ws.close(None).await.unwrap();
+        while ws.next().await.is_some() {}
+
+        assert!(
+            tokio::time::timeout(Duration::from_secs(2), listener.accept())
+                .await
+                .is_err()
+        );
+        assert!(client.get_version().await.unwrap_err().is_transport());
+    }
+
+    #[tokio::test]
+    async fn raw_notifications() {
+        let (client, server) = tokio::io::duplex(4096);
+        let ws = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        let client = Client::from_stream(ws, None, ClientConfig::default());
+        let mut raw = client.subscribe_raw_notifications();
+        let mut nots = client.subscribe_notifications();
+
+        for method in ["aria2.onDownloadUnknown", "aria2.onDownloadStart"] {
+            let not = json!({
+                "jsonrpc": "2.0",
+                "method": method,
+                "params": [{"gid": "2089b05ecca3d829"}],
+            });
+            server.send(Message::Text(not.to_string())).await.unwrap();
+        }
+
+        assert_eq!(raw.recv().await.unwrap().method, "aria2.onDownloadUnknown");
+        let req = raw.recv().await.unwrap();
+        assert_eq!(req.method, "aria2.onDownloadStart");
+        assert_eq!(req.params[0]["gid"], "2089b05ecca3d829");
+        // Only the known one is typed.
+        assert_eq!(nots.recv().await.unwrap().event, Event::Start);
+        assert!(nots.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn bt_complete_hook() {
+        let hooks: Hooks = Arc::new(Mutex::new((HashMap::new(), HashMap::new())));
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let task_hooks = TaskHooks::default()
+            .with_on_bt_complete({
+                let tx = tx.clone();
+                async move { tx.send("bt_complete").unwrap() }
+            })
+            .with_on_complete(async move { tx.send("complete").unwrap() });
+        hooks
+            .lock()
+            .unwrap()
+            .0
+            .insert("2089b05ecca3d829".to_string(), task_hooks);
+
+        // Seeding starts, and then stops.
+        let not = Notification::new("2089b05ecca3d829", "aria2.onBtDownloadComplete").unwrap();
+        process_nofitications(&not, &hooks).unwrap();
+        assert_eq!(rx.recv().await, Some("bt_complete"));
+        assert!(hooks.lock().unwrap().0.contains_key("2089b05ecca3d829"));
+        let not = Notification::new("2089b05ecca3d829", "aria2.onDownloadComplete").unwrap();
+        process_nofitications(&not, &hooks).unwrap();
+        assert_eq!(rx.recv().await, Some("complete"));
+        assert!(hooks.lock().unwrap().0.is_empty());
+    }
+
+    #[tokio::test]
+    async fn bt_complete_hook_dropped_without_bt() {
+        let hooks: Hooks = Arc::new(Mutex::new((HashMap::new(), HashMap::new())));
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let task_hooks = TaskHooks::default()
+            .with_on_bt_complete({
+                let tx = tx.clone();
+                async move { tx.send("bt_complete").unwrap() }
+            })
+            .with_on_complete(async move { tx.send("complete").unwrap() });
+        hooks
+            .lock()
+            .unwrap()
+            .0
+            .insert("2089b05ecca3d829".to_string(), task_hooks);
+
+        // Downloads other than torrents only complete.
+        let not = Notification::new("2089b05ecca3d829", "aria2.onDownloadComplete").unwrap();
+        process_nofitications(&not, &hooks).unwrap();
+        assert_eq!(rx.recv().await, Some("complete"));
+        // `on_bt_complete` is dropped with the hooks, so the channel closes.
+        assert_eq!(rx.recv().await, None);
+        assert!(hooks.lock().unwrap().0.is_empty());
+    }
+
+    #[tokio::test]
+    async fn session_stats() {
+        let (client, server) = tokio::io::duplex(4096);
+        let ws = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        let client = Client::from_stream(ws, None, ClientConfig::default());
+
+        tokio::spawn(async move {
+            let Some(Ok(Message::Text(s))) = server.next().await else {
+                return;
+            };
+            let req: Value = serde_json::from_str(&s).unwrap();
+            for method in [
+                "aria2.onDownloadStart",
+                "aria2.onDownloadStart",
+                "aria2.onDownloadComplete",
+                "aria2.onBtDownloadComplete",
+                "aria2.onDownloadError",
+            ] {
+                let not = json!({
+                    "jsonrpc": "2.0",
+                    "method": method,
+                    "params": [{"gid": "2089b05ecca3d829"}],
+                });
+                server.send(Message::Text(not.to_string())).await.unwrap();
+            }
+            let res = json!({"id": req["id"], "jsonrpc": "2.0", "result": "OK"});
+            server.send(Message::Text(res.to_string())).await.unwrap();
+        });
+
+        client.purge_download_result().await.unwrap();
+        assert_eq!(
+            client.session_stats(),
+            SessionStats {
+                started: 2,
+                completed: 1,
+                errored: 1,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn multicall_fallback() {
+        let (client, server) = tokio::io::duplex(4096);
+        let ws = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        let client = Client::from_stream(ws, None, ClientConfig::default());
+
+        let methods = tokio::spawn(async move {
+            let mut methods = Vec::new();
+            while let Some(Ok(Message::Text(s))) = server.next().await {
+                let req: Value = serde_json::from_str(&s).unwrap();
+                let method = req["method"].as_str().unwrap().to_string();
+                let res = match method.as_str() {
+                    "system.multicall" => json!({
+                        "id": req["id"],
+                        "jsonrpc": "2.0",
+                        "error": {"code": 1, "message": "No such method: system.multicall"},
+                    }),
+                    "aria2.tellStatus" => json!({
+                        "id": req["id"],
+                        "jsonrpc": "2.0",
+                        "error": {"code": 1, "message": "GID 0000000000000001 is not found"},
+                    }),
+                    _ => json!({"id": req["id"], "jsonrpc": "2.0", "result": "OK"}),
+                };
+                server.send(Message::Text(res.to_string())).await.unwrap();
+                methods.push(method);
+            }
+            methods
+        });
+
+        // Nothing is sent for an empty batch.
+        assert!(client.multicall(vec![], None).await.unwrap().is_empty());
+        for _ in 0..2 {
+            let results = client
+                .0
+                .multicall(
+                    vec![
+                        ("purgeDownloadResult", vec![]),
+                        ("tellStatus", vec![json!("0000000000000001")]),
+                    ],
+                    None,
+                )
+                .await
+                .unwrap();
+            assert_eq!(results[0].as_ref().unwrap(), "OK");
+            assert!(matches!(results[1], Err(Error::GidNotFound { .. })));
+        }
+        drop(client);
+
+        // `system.multicall` is only tried once.
+        assert_eq!(
+            methods.await.unwrap(),
+            [
+                "system.multicall",
+                "aria2.purgeDownloadResult",
+                "aria2.tellStatus",
+                "aria2.purgeDownloadResult",
+                "aria2.tellStatus",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn ping_pong() {
+        let (client, server) = tokio::io::duplex(4096);
+        let ws = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        let client = Client::from_stream(ws, None, ClientConfig::default());
+        let mut rx = client.subscribe_notifications();
+
+        server.send(Message::Ping(b"ping".to_vec())).await.unwrap();
+        server
+            .send(Message::Text(NOTIFICATION.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap().event, Event::Start);
+        assert_eq!(
+            server.next().await.unwrap().unwrap(),
+            Message::Pong(b"ping".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn keepalive() {
+        let (client, server) = tokio::io::duplex(4096);
+        let ws = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        let mut server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        let config = ClientConfig {
+            keepalive_interval: Some(Duration::from_millis(10)),
+            ..Default::default()
+        };
+        let _client = Client::from_stream(ws, None, config);
+
+        for _ in 0..2 {
+            assert_eq!(
+                server.next().await.unwrap().unwrap(),
+                Message::Ping(Vec::new())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn keepalive_timeout() {
+        let (client, server) = tokio::io::duplex(4096);
+        let ws = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        // The server never reads, so pings are never answered.
+        let _server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        let config = ClientConfig {
+            keepalive_interval: Some(Duration::from_millis(10)),
+            keepalive_timeout: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let client = Client::from_stream(ws, None, config);
+
+        let res = client
+            .call_and_subscribe::<Value>("getVersion", vec![], Some(Duration::from_secs(5)))
+            .await;
+        assert!(matches!(res, Err(Error::Disconnected)), "{:?}", res);
+    }
 }
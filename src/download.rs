@@ -0,0 +1,210 @@
+use std::{collections::BTreeSet, path::PathBuf};
+
+use crate::{
+    response::{BitTorrentFileMode, Status, TaskStatus},
+    Client, Error, TaskOptions,
+};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A handle to a single download task, identified by its GID.
+///
+/// Created by [`Client::download`]. The handle can be cloned and is cheap to keep around.
+///
+/// The handle keeps the status fetched by the last [`Download::refresh`].
+/// Accessors like [`Download::progress`] read that snapshot without querying aria2,
+/// so they are as stale as the last refresh, and `None` before the first one.
+#[derive(Clone)]
+pub struct Download {
+    client: Client,
+    gid: String,
+    status: Option<Status>,
+}
+
+impl Client {
+    /// Get a [`Download`] handle for the task with given gid.
+    pub fn download(&self, gid: &str) -> Download {
+        Download {
+            client: self.clone(),
+            gid: gid.to_string(),
+            status: None,
+        }
+    }
+}
+
+impl Download {
+    pub fn gid(&self) -> &str {
+        &self.gid
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Fetch the latest status by `tell_status`, and keep it as the snapshot.
+    ///
+    /// The previous snapshot is kept if the call fails.
+    pub async fn refresh(&mut self) -> Result<&Status> {
+        let status = self.client.tell_status(&self.gid).await?;
+        Ok(self.status.insert(status))
+    }
+
+    /// The status fetched by the last [`Download::refresh`].
+    pub fn status(&self) -> Option<&Status> {
+        self.status.as_ref()
+    }
+
+    /// Downloaded fraction from 0.0 to 1.0 in the snapshot.
+    ///
+    /// It's 0.0 if the total length is unknown yet.
+    pub fn progress(&self) -> Option<f64> {
+        let status = self.status.as_ref()?;
+        Some(match status.total_length {
+            0 => 0.0,
+            total => status.completed_length as f64 / total as f64,
+        })
+    }
+
+    /// Download speed in bytes/sec in the snapshot.
+    pub fn speed(&self) -> Option<u64> {
+        self.status.as_ref().map(|s| s.download_speed)
+    }
+
+    /// Whether the task is complete in the snapshot.
+    ///
+    /// `false` if there is no snapshot yet.
+    pub fn is_complete(&self) -> bool {
+        self.status
+            .as_ref()
+            .is_some_and(|s| s.status == TaskStatus::Complete)
+    }
+
+    /// Stop seeding a completed torrent.
+    ///
+    /// aria2 has no dedicated method for this,
+    /// so the task is removed by `aria2.remove`.
+    /// The downloaded files are kept on the disk.
+    pub async fn stop_seeding(&self) -> Result<()> {
+        self.client.remove(&self.gid).await
+    }
+
+    /// Indices of selected files, in ascending order.
+    ///
+    /// All files are selected if `select-file` is not set.
+    pub async fn selected_files(&self) -> Result<Vec<u32>> {
+        Ok(self
+            .client
+            .get_files(&self.gid)
+            .await?
+            .into_iter()
+            .filter(|f| f.selected)
+            .map(|f| f.index)
+            .collect())
+    }
+
+    /// Only download files with given indices.
+    ///
+    /// aria2 treats an empty selection as selecting all files.
+    ///
+    /// Changing the selection of an active download makes aria2 restart it,
+    /// so pieces in progress may be paused and resumed.
+    pub async fn select_files(&self, indices: &[u32]) -> Result<()> {
+        let indices: BTreeSet<u32> = indices.iter().copied().collect();
+        let select_file = indices
+            .iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        self.client
+            .change_option(
+                &self.gid,
+                TaskOptions {
+                    select_file: Some(select_file),
+                    ..Default::default()
+                },
+            )
+            .await
+    }
+
+    /// Add files with given indices to the current selection.
+    pub async fn add_files(&self, indices: &[u32]) -> Result<()> {
+        let mut selected = self.selected_files().await?;
+        selected.extend_from_slice(indices);
+        self.select_files(&selected).await
+    }
+
+    /// Remove files with given indices from the current selection.
+    ///
+    /// If all files are removed, aria2 will select all files again.
+    pub async fn remove_files(&self, indices: &[u32]) -> Result<()> {
+        let mut selected = self.selected_files().await?;
+        selected.retain(|i| !indices.contains(i));
+        self.select_files(&selected).await
+    }
+
+    /// Replace all URIs of the file with given index by `new_uris`.
+    ///
+    /// The current URIs are fetched by `aria2.getFiles`,
+    /// then deleted and replaced in a single `aria2.changeUri` call.
+    /// Returns the number of URIs deleted and added.
+    pub async fn replace_uris(&self, file_index: u32, new_uris: Vec<String>) -> Result<(i32, i32)> {
+        // aria2 deletes one occurrence per entry, so duplicated URIs are listed as is.
+        let del_uris = self
+            .client
+            .get_files(&self.gid)
+            .await?
+            .into_iter()
+            .find(|f| f.index == file_index)
+            .map(|f| f.uris.into_iter().map(|u| u.uri).collect())
+            .unwrap_or_default();
+        self.client
+            .change_uri(&self.gid, file_index as i32, del_uris, new_uris, None)
+            .await
+    }
+
+    /// Remove the task, and return paths of its files.
+    ///
+    /// aria2 keeps the files on the disk after removing a task,
+    /// and there is no RPC method to delete them.
+    /// The paths are on the host running aria2, so the caller can delete them if it has access.
+    pub async fn remove_and_report_files(&self) -> Result<Vec<PathBuf>> {
+        let status = self.client.tell_status(&self.gid).await?;
+        self.client.remove(&self.gid).await?;
+        Ok(file_paths(&status))
+    }
+
+    /// Same as [`Download::remove_and_report_files`],
+    /// but the path of the `.aria2` control file is included as the last item.
+    pub async fn remove_and_report_all_files(&self) -> Result<Vec<PathBuf>> {
+        let status = self.client.tell_status(&self.gid).await?;
+        self.client.remove(&self.gid).await?;
+        let mut paths = file_paths(&status);
+        paths.extend(control_file_path(&status));
+        Ok(paths)
+    }
+}
+
+fn file_paths(status: &Status) -> Vec<PathBuf> {
+    status
+        .files
+        .iter()
+        .filter(|f| !f.path.is_empty())
+        .map(|f| PathBuf::from(&f.path))
+        .collect()
+}
+
+/// aria2 saves the control file next to the file,
+/// or next to the top directory of a multi-file torrent.
+fn control_file_path(status: &Status) -> Option<PathBuf> {
+    let bittorrent = status.bittorrent.as_ref();
+    let path = match (
+        bittorrent.and_then(|bt| bt.mode.as_ref()),
+        bittorrent.and_then(|bt| bt.info.as_ref()),
+    ) {
+        (Some(BitTorrentFileMode::Multi), Some(info)) => status.output_dir()?.join(&info.name),
+        _ => PathBuf::from(&status.files.first().filter(|f| !f.path.is_empty())?.path),
+    };
+    let mut path = path.into_os_string();
+    path.push(".aria2");
+    Some(path.into())
+}
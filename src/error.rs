@@ -10,9 +10,21 @@ use crate::RpcResponse;
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(crate)))]
 pub enum Error {
+    /// An error response from aria2.
+    ///
+    /// `source` carries the `code` and `message` of the JSON-RPC error,
+    /// and is the way to match on specific failures, like `Error::GidNotFound` does.
     Aria2 {
         source: crate::Aria2Error,
     },
+    /// The task is not found, e.g. it's been purged from the stopped list.
+    ///
+    /// `source` carries the `code` and `message` like `Error::Aria2`.
+    #[snafu(display("aria2: GID {gid} is not found"))]
+    GidNotFound {
+        gid: String,
+        source: crate::Aria2Error,
+    },
     #[snafu(display("aria2: unexpected message received: {message:?}"))]
     UnexpectedMessage {
         message: String,
@@ -39,4 +51,150 @@ pub enum Error {
     ReconnectHook {
         message: String,
     },
+    /// The operation was cancelled by the stop signal passed by the caller.
+    #[snafu(display("aria2: operation cancelled"))]
+    Cancelled,
+    /// The connection was lost before the response was received,
+    /// or the client has stopped connecting, see [`crate::ClientConfig::reconnect`].
+    #[snafu(display("aria2: disconnected"))]
+    Disconnected,
+    /// The option can't be changed after the task is added, returned by [`crate::Client::change_option`].
+    #[snafu(display("aria2: option {key} can't be changed by changeOption"))]
+    OptionNotChangeable {
+        key: String,
+    },
+    /// The content passed to [`crate::Client::add_torrent`] is not a torrent file.
+    #[snafu(display("aria2: invalid torrent file"))]
+    InvalidTorrent,
+    /// No gid is given to a helper waiting for one of the tasks,
+    /// like [`crate::Client::wait_for_any_complete`].
+    #[snafu(display("aria2: no tasks to wait for"))]
+    NoTasks,
+    /// The task is not a BitTorrent download, returned by [`crate::Client::get_torrent_peers`].
+    #[snafu(display("aria2: task {gid} is not a BitTorrent download"))]
+    NotATorrent {
+        gid: String,
+    },
+}
+
+impl Error {
+    /// Convert an error response to `Error::GidNotFound` if possible, otherwise `Error::Aria2`.
+    pub(crate) fn from_aria2(err: crate::Aria2Error) -> Self {
+        match gid_not_found(&err) {
+            Some(gid) => Error::GidNotFound { gid, source: err },
+            None => Error::Aria2 { source: err },
+        }
+    }
+
+    /// Whether the error is about the connection rather than the request,
+    /// e.g. a websocket error, a timeout or a lost connection.
+    pub fn is_transport(&self) -> bool {
+        matches!(
+            self,
+            Error::Websocket { .. }
+                | Error::Timeout { .. }
+                | Error::OneshotRecv { .. }
+                | Error::MpscSendMessage { .. }
+                | Error::Disconnected
+        )
+    }
+
+    /// Whether the error is responded by aria2, like `Error::Aria2` and `Error::GidNotFound`.
+    pub fn is_aria2(&self) -> bool {
+        matches!(self, Error::Aria2 { .. } | Error::GidNotFound { .. })
+    }
+
+    /// The code and message responded by aria2, for `Error::Aria2` and `Error::GidNotFound`.
+    pub fn aria2_error(&self) -> Option<&crate::Aria2Error> {
+        match self {
+            Error::Aria2 { source } | Error::GidNotFound { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+
+    /// Whether aria2 rejected the call because the token is missing or wrong.
+    ///
+    /// aria2 responds it with code 1 like other failures, so the message `Unauthorized` is checked.
+    pub fn is_unauthorized(&self) -> bool {
+        self.aria2_error()
+            .is_some_and(|err| err.message == "Unauthorized")
+    }
+
+    /// Whether the same request may succeed if retried, which is the case for transport errors.
+    ///
+    /// Errors responded by aria2, malformed messages, invalid input and cancellations
+    /// are not retriable.
+    /// Note that a request failed by a timeout or disconnection may have been processed by aria2,
+    /// so retrying a non-idempotent call like `add_uri` may add the download twice.
+    pub fn is_retriable(&self) -> bool {
+        self.is_transport()
+    }
+}
+
+/// Extract the gid from errors like `GID 2089b05ecca3d829 is not found`
+/// or `No such download for GID#2089b05ecca3d829`.
+///
+/// aria2 responds all RPC failures with code 1, so the message is checked as well.
+fn gid_not_found(err: &crate::Aria2Error) -> Option<String> {
+    if err.code != 1 {
+        return None;
+    }
+    let (_, rest) = err.message.split_once("GID")?;
+    let gid = rest
+        .trim_start_matches([' ', '#'])
+        .split_whitespace()
+        .next()?;
+    if gid.is_empty() || !gid.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let message = err.message.to_ascii_lowercase();
+    if message.contains("not found") || message.contains("no such download") {
+        Some(gid.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::gid_not_found;
+    use crate::{Aria2Error, Error};
+
+    fn err(code: i32, message: &str) -> Aria2Error {
+        Aria2Error {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn gid_not_found_message() {
+        assert_eq!(
+            gid_not_found(&err(1, "GID 2089b05ecca3d829 is not found")).as_deref(),
+            Some("2089b05ecca3d829")
+        );
+        assert_eq!(
+            gid_not_found(&err(1, "No such download for GID#2089b05ecca3d829")).as_deref(),
+            Some("2089b05ecca3d829")
+        );
+        assert_eq!(gid_not_found(&err(1, "Invalid GID 2089b05ecca3d829")), None);
+        assert_eq!(
+            gid_not_found(&err(2, "GID 2089b05ecca3d829 is not found")),
+            None
+        );
+    }
+
+    #[test]
+    fn classification() {
+        assert!(Error::Disconnected.is_retriable());
+        assert!(!Error::Disconnected.is_aria2());
+        let aria2 = Error::from_aria2(err(1, "GID 2089b05ecca3d829 is not found"));
+        assert!(aria2.is_aria2());
+        assert!(!aria2.is_retriable());
+        assert!(!Error::Cancelled.is_retriable());
+        assert!(!Error::InvalidTorrent.is_transport());
+        assert_eq!(aria2.aria2_error().unwrap().code, 1);
+        assert!(Error::from_aria2(err(1, "Unauthorized")).is_unauthorized());
+        assert!(!aria2.is_unauthorized());
+    }
 }
@@ -0,0 +1,1191 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::{stream, Future, Stream, StreamExt};
+use log::info;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use serde_with::serde_as;
+use snafu::{ensure, ResultExt};
+use tokio::{
+    select, spawn,
+    sync::broadcast::{self, error::RecvError},
+    task::JoinHandle,
+    time::{self, Instant, Interval, MissedTickBehavior},
+};
+
+use crate::{
+    error,
+    response::{self, Event, Notification, StateChange, TaskStatus},
+    utils::{saturating_i32, NumberOrString},
+    Client, Error, TaskHooks, TaskOptions,
+};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Number of tasks requested by each call of auto-paginating helpers.
+const PAGE_SIZE: i32 = 1000;
+
+/// How often [`Client::watch_many`] fetches statuses without notifications.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often [`Client::add_uri_when_slot_free`] checks for a free slot without notifications,
+/// e.g. after `max-concurrent-downloads` is raised.
+const SLOT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Run `fut` until `stop` resolves.
+///
+/// Returns `Error::Cancelled` if `stop` resolves first,
+/// in which case `fut` is dropped.
+///
+/// This can be used to stop long-running helpers like [`Client::wait_for_complete`]
+/// without dropping the client, e.g. with a `Notify` or a `oneshot::Receiver`.
+pub async fn cancellable<T>(fut: impl Future<Output = Result<T>>, stop: impl Future) -> Result<T> {
+    select! {
+        r = fut => r,
+        _ = stop => Err(Error::Cancelled),
+    }
+}
+
+/// The status of a task after the event, if the event changes it.
+fn status_after(event: Event) -> Option<TaskStatus> {
+    match event {
+        Event::Start => Some(TaskStatus::Active),
+        Event::Pause => Some(TaskStatus::Paused),
+        Event::Stop => Some(TaskStatus::Removed),
+        Event::Complete => Some(TaskStatus::Complete),
+        Event::Error => Some(TaskStatus::Error),
+        // Still active while seeding.
+        Event::BtComplete => None,
+    }
+}
+
+/// State of the stream returned by [`Client::subscribe_state_changes`].
+struct StateTracker {
+    client: Client,
+    rx: broadcast::Receiver<Notification>,
+    interval: Interval,
+    states: HashMap<String, TaskStatus>,
+    changes: VecDeque<StateChange>,
+    seeded: bool,
+}
+
+impl StateTracker {
+    fn update(&mut self, gid: &str, status: TaskStatus) {
+        let old = self.states.insert(gid.to_string(), status.clone());
+        if old.as_ref() != Some(&status) {
+            self.changes.push_back(StateChange {
+                gid: gid.into(),
+                old,
+                new: status,
+            });
+        }
+    }
+
+    /// Compare the known statuses with statuses of all tasks in aria2.
+    async fn reconcile(&mut self) -> Result<()> {
+        let current = self.client.all_statuses().await?;
+        if self.seeded {
+            for (gid, status) in &current {
+                self.update(gid, status.clone());
+            }
+        }
+        // Tasks purged from aria2 are forgotten silently.
+        self.states = current;
+        self.seeded = true;
+        Ok(())
+    }
+
+    async fn next(&mut self) -> Option<Result<StateChange>> {
+        loop {
+            if let Some(change) = self.changes.pop_front() {
+                return Some(Ok(change));
+            }
+            let res = select! {
+                r = self.rx.recv() => match r {
+                    Ok(not) => {
+                        if let Some(status) = status_after(not.event) {
+                            self.update(&not.gid, status);
+                        }
+                        Ok(())
+                    }
+                    // Some notifications are missed. Check all statuses again.
+                    Err(RecvError::Lagged(_)) => self.reconcile().await,
+                    Err(RecvError::Closed) => return None,
+                },
+                _ = self.interval.tick() => self.reconcile().await,
+            };
+            if let Err(err) = res {
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+/// How [`Client::share_bandwidth`] splits the total speed among active tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpeedShare {
+    /// Every task gets the same limit.
+    #[default]
+    Equal,
+    /// Tasks get limits proportional to their current download speeds.
+    Proportional,
+}
+
+/// A download added by [`Client::add_uris_ordered`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct OrderedUri {
+    pub uris: Vec<String>,
+    pub options: Option<TaskOptions>,
+    /// Position in the waiting queue after all items are added, starting from 0.
+    pub position: u32,
+}
+
+/// Positions to insert items at one by one, in ascending order,
+/// so each item ends up at its requested position.
+///
+/// An item whose position is taken by a previous item is placed right after it.
+fn insert_positions(positions: &[u32]) -> Vec<(usize, u32)> {
+    let mut order: Vec<usize> = (0..positions.len()).collect();
+    order.sort_by_key(|&i| positions[i]);
+    let mut next = 0;
+    order
+        .into_iter()
+        .map(|i| {
+            let position = positions[i].max(next);
+            next = position + 1;
+            (i, position)
+        })
+        .collect()
+}
+
+/// Split `target` bytes/sec among tasks with given speeds.
+///
+/// Limits are at least 1, since 0 means unrestricted to aria2.
+fn split_speed(target: u64, speeds: &[u64], share: SpeedShare) -> Vec<u64> {
+    let total: u64 = speeds.iter().sum();
+    speeds
+        .iter()
+        .map(|&speed| match share {
+            SpeedShare::Proportional if total > 0 => {
+                (target as u128 * speed as u128 / total as u128) as u64
+            }
+            _ => target / speeds.len() as u64,
+        })
+        .map(|limit| limit.max(1))
+        .collect()
+}
+
+/// Recent download speeds of a task, sampled by [`Client::start_speed_sampler`].
+///
+/// Sampling stops when the task stops, or when this handle is dropped.
+pub struct SpeedHistory {
+    samples: Arc<Mutex<VecDeque<u64>>>,
+    task: JoinHandle<()>,
+}
+
+impl SpeedHistory {
+    /// Sampled speeds in bytes/sec, from the oldest to the latest.
+    pub fn samples(&self) -> Vec<u64> {
+        self.samples.lock().unwrap().iter().copied().collect()
+    }
+
+    /// The latest sampled speed.
+    pub fn latest(&self) -> Option<u64> {
+        self.samples.lock().unwrap().back().copied()
+    }
+
+    /// Whether the sampler is still polling.
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+}
+
+impl Drop for SpeedHistory {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SpeedSample {
+    status: TaskStatus,
+    #[serde_as(as = "NumberOrString")]
+    download_speed: u64,
+}
+
+fn is_finished(status: &TaskStatus) -> bool {
+    matches!(
+        status,
+        TaskStatus::Complete | TaskStatus::Error | TaskStatus::Removed
+    )
+}
+
+impl Client {
+    /// Wait for the task to stop, and return its final status.
+    ///
+    /// The task is stopped when it's completed, errored or removed.
+    /// A torrent that is still seeding is not considered completed.
+    ///
+    /// Use [`cancellable`] to stop waiting.
+    pub async fn wait_for_complete(&self, gid: &str) -> Result<response::Status> {
+        let (_, status) = self.wait_for_any_complete(&[gid], true).await?;
+        Ok(status)
+    }
+
+    /// Wait for the first task in `gids` to stop, and return its gid and final status.
+    ///
+    /// If `error_is_done` is `false`, errored or removed tasks are ignored
+    /// in favor of a later completed one.
+    /// If all tasks end up unsuccessful, the last one of them is returned.
+    ///
+    /// Other tasks are left untouched.
+    ///
+    /// Returns `Error::NoTasks` if `gids` is empty.
+    pub async fn wait_for_any_complete(
+        &self,
+        gids: &[&str],
+        error_is_done: bool,
+    ) -> Result<(String, response::Status)> {
+        ensure!(!gids.is_empty(), error::NoTasksSnafu);
+        // subscribe before checking the status
+        let mut rx = self.subscribe_notifications();
+        let mut pending: HashSet<&str> = gids.iter().copied().collect();
+        let mut to_check: Vec<&str> = gids.to_vec();
+        let mut last_failed = None;
+        loop {
+            for gid in to_check.drain(..) {
+                let status = self.tell_status(gid).await?;
+                if status.status == TaskStatus::Complete
+                    || (error_is_done && is_finished(&status.status))
+                {
+                    return Ok((gid.to_string(), status));
+                }
+                if is_finished(&status.status) {
+                    pending.remove(gid);
+                    last_failed = Some((gid.to_string(), status));
+                }
+            }
+            if pending.is_empty() {
+                if let Some(r) = last_failed {
+                    return Ok(r);
+                }
+            }
+            loop {
+                match rx.recv().await {
+                    Ok(not) => {
+                        if let Some(gid) = pending.get(not.gid.as_str()) {
+                            if matches!(not.event, Event::Complete | Event::Error | Event::Stop) {
+                                to_check.push(gid);
+                                break;
+                            }
+                        }
+                    }
+                    // Some notifications are missed. Check all statuses again.
+                    Err(RecvError::Lagged(_)) => {
+                        to_check.extend(pending.iter());
+                        break;
+                    }
+                    Err(RecvError::Closed) => return Err(Error::Disconnected),
+                }
+            }
+        }
+    }
+
+    /// Get a stream of status transitions of all tasks.
+    ///
+    /// Statuses are tracked from notifications, and reconciled with statuses
+    /// of all tasks every `reconcile_interval`, or when notifications are missed.
+    /// A change is only yielded when the status of a task actually changes,
+    /// so repeated notifications of the same status are ignored.
+    ///
+    /// Statuses of the tasks existing when the stream is first polled are not yielded.
+    /// Tasks seen for the first time are yielded with `old` being `None`.
+    ///
+    /// Errors of reconciliation are yielded without ending the stream.
+    /// The stream ends when the client is dropped.
+    pub fn subscribe_state_changes(
+        &self,
+        reconcile_interval: Duration,
+    ) -> impl Stream<Item = Result<StateChange>> + Send + 'static {
+        let mut interval = time::interval(reconcile_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let tracker = StateTracker {
+            client: self.clone(),
+            rx: self.subscribe_notifications(),
+            interval,
+            states: HashMap::new(),
+            changes: VecDeque::new(),
+            seeded: false,
+        };
+        stream::unfold(tracker, |mut tracker| async move {
+            let item = tracker.next().await?;
+            Some((item, tracker))
+        })
+    }
+
+    /// Get a stream of `get_global_stat` results, polled every `interval`.
+    ///
+    /// If `dedup` is `true`, a result equal to the previous one is skipped.
+    ///
+    /// Failed polls are logged and skipped, so the stream never ends.
+    /// Drop it to stop polling.
+    pub fn global_stat_stream(
+        &self,
+        interval: Duration,
+        dedup: bool,
+    ) -> impl Stream<Item = response::GlobalStat> + Send + 'static {
+        let mut interval = time::interval(interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let state = (self.clone(), interval, None::<response::GlobalStat>);
+        stream::unfold(state, move |(client, mut interval, mut last)| async move {
+            loop {
+                interval.tick().await;
+                let stat = match client.get_global_stat().await {
+                    Ok(stat) => stat,
+                    Err(err) => {
+                        info!("aria2: failed to get global stat: {}", err);
+                        continue;
+                    }
+                };
+                if dedup && last.as_ref() == Some(&stat) {
+                    continue;
+                }
+                last = Some(stat.clone());
+                return Some((stat, (client, interval, last)));
+            }
+        })
+    }
+
+    /// Poll the download speed of the task every `interval`,
+    /// and keep the latest `window` samples, at least 1.
+    ///
+    /// Sampling stops when the task is complete, errored, removed or not found.
+    /// Other failed polls are logged and skipped.
+    pub fn start_speed_sampler(
+        &self,
+        gid: &str,
+        window: usize,
+        interval: Duration,
+    ) -> SpeedHistory {
+        let window = window.max(1);
+        let samples = Arc::new(Mutex::new(VecDeque::with_capacity(window)));
+        let mut interval = time::interval(interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let keys = vec!["status".to_string(), "downloadSpeed".to_string()];
+        let (client, gid, buf) = (self.clone(), gid.to_string(), samples.clone());
+
+        let task = spawn(async move {
+            loop {
+                interval.tick().await;
+                let sample = match client.custom_tell_status(&gid, Some(keys.clone())).await {
+                    Ok(map) => match serde_json::from_value::<SpeedSample>(Value::Object(map)) {
+                        Ok(sample) => sample,
+                        Err(err) => {
+                            info!("aria2: unexpected status of {}: {}", gid, err);
+                            continue;
+                        }
+                    },
+                    Err(Error::GidNotFound { .. }) => return,
+                    Err(err) => {
+                        info!("aria2: failed to sample speed of {}: {}", gid, err);
+                        continue;
+                    }
+                };
+                {
+                    let mut buf = buf.lock().unwrap();
+                    if buf.len() == window {
+                        buf.pop_front();
+                    }
+                    buf.push_back(sample.download_speed);
+                }
+                if is_finished(&sample.status) {
+                    return;
+                }
+            }
+        });
+        SpeedHistory { samples, task }
+    }
+
+    /// Get a stream of statuses of the tasks with given gids.
+    ///
+    /// A status is fetched and yielded when a notification of the task is received,
+    /// and for all pending tasks every few seconds as a backstop for missed notifications.
+    /// Tasks are no longer watched once they are complete, errored, removed or not found,
+    /// and the stream ends when no task is left.
+    ///
+    /// Failed fetches are logged and skipped.
+    pub fn watch_many(
+        &self,
+        gids: &[&str],
+    ) -> impl Stream<Item = (String, response::Status)> + Send + 'static {
+        // subscribe before fetching the statuses
+        let rx = self.subscribe_notifications();
+        let pending: HashSet<String> = gids.iter().map(|gid| gid.to_string()).collect();
+        let to_fetch: VecDeque<String> = pending.iter().cloned().collect();
+        let interval = time::interval_at(Instant::now() + WATCH_POLL_INTERVAL, WATCH_POLL_INTERVAL);
+        let state = (self.clone(), rx, pending, to_fetch, interval);
+        stream::unfold(
+            state,
+            |(client, mut rx, mut pending, mut to_fetch, mut interval)| async move {
+                loop {
+                    if let Some(gid) = to_fetch.pop_front() {
+                        match client.tell_status(&gid).await {
+                            Ok(status) => {
+                                if is_finished(&status.status) {
+                                    pending.remove(&gid);
+                                }
+                                return Some((
+                                    (gid, status),
+                                    (client, rx, pending, to_fetch, interval),
+                                ));
+                            }
+                            Err(Error::GidNotFound { .. }) => {
+                                pending.remove(&gid);
+                            }
+                            Err(err) => info!("aria2: failed to get status of {}: {}", gid, err),
+                        }
+                        continue;
+                    }
+                    if pending.is_empty() {
+                        return None;
+                    }
+                    let gids: Vec<&String> = select! {
+                        _ = interval.tick() => pending.iter().collect(),
+                        r = rx.recv() => match r {
+                            Ok(not) => pending.get(not.gid.as_str()).into_iter().collect(),
+                            Err(RecvError::Lagged(_)) => pending.iter().collect(),
+                            Err(RecvError::Closed) => return None,
+                        },
+                    };
+                    for gid in gids {
+                        if !to_fetch.contains(gid) {
+                            to_fetch.push_back(gid.clone());
+                        }
+                    }
+                }
+            },
+        )
+    }
+
+    /// Get a stream of gids of completed tasks, from notifications.
+    ///
+    /// If `include_bt_complete` is `true`, torrents are yielded
+    /// when the download is complete but seeding is still going on.
+    /// Such torrents are yielded again when they stop seeding.
+    ///
+    /// Tasks completed while notifications are missed (see [`Client::dropped_notification_count`])
+    /// are not yielded.
+    /// The stream ends when the client is dropped.
+    pub fn completed_stream(
+        &self,
+        include_bt_complete: bool,
+    ) -> impl Stream<Item = response::Gid> + Send + 'static {
+        let rx = self.subscribe_notifications();
+        stream::unfold(rx, move |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(not) => {
+                        if not.event == Event::Complete
+                            || (include_bt_complete && not.event == Event::BtComplete)
+                        {
+                            return Some((not.gid, rx));
+                        }
+                    }
+                    Err(RecvError::Lagged(n)) => {
+                        info!("aria2: {} notifications missed by completed_stream", n);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Same as [`Client::completed_stream`], but yield the status of each completed task.
+    ///
+    /// The stream holds a clone of the client, so it doesn't end until dropped.
+    pub fn completed_status_stream(
+        &self,
+        include_bt_complete: bool,
+    ) -> impl Stream<Item = Result<response::Status>> + Send + 'static {
+        let client = self.clone();
+        self.completed_stream(include_bt_complete).then(move |gid| {
+            let client = client.clone();
+            async move { client.tell_status(&gid).await }
+        })
+    }
+
+    /// Get a stream of notifications of the task with given gid.
+    ///
+    /// The stream doesn't end when the task stops, or if the task never sends notifications.
+    /// It ends when the client is dropped.
+    pub fn subscribe_gid(&self, gid: &str) -> impl Stream<Item = Notification> + Send + 'static {
+        self.subscribe_gids(&[gid])
+    }
+
+    /// Same as [`Client::subscribe_gid`], but for notifications of any of the given tasks.
+    pub fn subscribe_gids(
+        &self,
+        gids: &[&str],
+    ) -> impl Stream<Item = Notification> + Send + 'static {
+        let gids: HashSet<String> = gids.iter().map(|gid| gid.to_string()).collect();
+        let rx = self.subscribe_notifications();
+        stream::unfold(rx, move |mut rx| {
+            let gids = gids.clone();
+            async move {
+                loop {
+                    match rx.recv().await {
+                        Ok(not) => {
+                            if gids.contains(not.gid.as_str()) {
+                                return Some((not, rx));
+                            }
+                        }
+                        Err(RecvError::Lagged(n)) => {
+                            info!("aria2: {} notifications missed by subscribe_gids", n);
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        })
+    }
+
+    /// Same as `get_files`, but sorted by the index of each file.
+    pub async fn get_files_sorted(&self, gid: &str) -> Result<Vec<response::File>> {
+        let mut files = self.get_files(gid).await?;
+        files.sort_by_key(|f| f.index);
+        Ok(files)
+    }
+
+    /// Wait until aria2 has no active downloads, and no waiting ones except paused ones.
+    ///
+    /// The stat is checked again on every notification, and every `poll_interval` as a backstop
+    /// for missed notifications.
+    ///
+    /// Returns `Error::Timeout` if aria2 isn't idle within `timeout`.
+    pub async fn wait_until_idle(
+        &self,
+        poll_interval: Duration,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        let wait = async {
+            // subscribe before checking the stat
+            let mut rx = self.subscribe_notifications();
+            let mut interval = time::interval(poll_interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                if self.is_idle().await? {
+                    return Ok(());
+                }
+                select! {
+                    _ = interval.tick() => {},
+                    r = rx.recv() => {
+                        if let Err(RecvError::Closed) = r {
+                            return Err(Error::Disconnected);
+                        }
+                    },
+                }
+            }
+        };
+        match timeout {
+            Some(timeout) => time::timeout(timeout, wait)
+                .await
+                .context(error::TimeoutSnafu)?,
+            None => wait.await,
+        }
+    }
+
+    /// Shut down aria2 after active downloads finish, or forcibly after `timeout`.
+    ///
+    /// The sequence is:
+    ///
+    /// 1. Pause waiting downloads so no new download starts.
+    ///    They are saved as paused in the session.
+    /// 2. Wait for active downloads to stop like [`Client::wait_until_idle`], up to `timeout`.
+    /// 3. Save the session by `save_session`.
+    /// 4. Call `shutdown`, or `force_shutdown` if the timeout elapsed,
+    ///    which abandons downloads still active.
+    ///
+    /// Returns whether all active downloads stopped before the timeout.
+    /// Errors of the calls made meanwhile, including their own timeouts, are returned as is.
+    pub async fn shutdown_after_drain(&self, timeout: Duration) -> Result<bool> {
+        let stat = self.get_global_stat().await?;
+        if stat.num_waiting > 0 {
+            let waiting = self
+                .tell_waiting(0, saturating_i32(stat.num_waiting))
+                .await?;
+            self.call_for_each("pause", waiting, |s| s.status == TaskStatus::Waiting)
+                .await?;
+        }
+        // The deadline is applied here, so timeouts of the calls inside are returned as errors.
+        let drained = match time::timeout(
+            timeout,
+            self.wait_until_idle(Duration::from_secs(1), None),
+        )
+        .await
+        {
+            Ok(res) => {
+                res?;
+                true
+            }
+            Err(_) => false,
+        };
+        self.save_session().await?;
+        if drained {
+            self.shutdown().await?;
+        } else {
+            info!("aria2: downloads are still active, shutting down forcibly");
+            self.force_shutdown().await?;
+        }
+        Ok(drained)
+    }
+
+    /// Whether there are no active downloads, and all waiting ones are paused.
+    async fn is_idle(&self) -> Result<bool> {
+        let stat = self.get_global_stat().await?;
+        if stat.num_active > 0 {
+            return Ok(false);
+        }
+        if stat.num_waiting == 0 {
+            return Ok(true);
+        }
+        // Paused downloads are counted as waiting.
+        let waiting = self
+            .custom_tell_waiting(
+                0,
+                saturating_i32(stat.num_waiting),
+                Some(vec!["status".to_string()]),
+            )
+            .await?;
+        Ok(waiting
+            .iter()
+            .all(|map| map.get("status").and_then(Value::as_str) == Some("paused")))
+    }
+
+    /// Wait until fewer than `max-concurrent-downloads` downloads are active,
+    /// then add the download like `add_uri`.
+    ///
+    /// The concurrency is checked again when a download stops or pauses,
+    /// and periodically in case the limit is raised.
+    /// Another client may take the slot between the check and the add,
+    /// in which case the download just waits in the queue.
+    ///
+    /// Returns `Error::Timeout` without adding the download if no slot is free within `timeout`.
+    pub async fn add_uri_when_slot_free(
+        &self,
+        uris: Vec<String>,
+        options: impl Into<Option<TaskOptions>>,
+        hooks: Option<TaskHooks>,
+        timeout: Option<Duration>,
+    ) -> Result<String> {
+        let wait = async {
+            // subscribe before checking the concurrency
+            let mut rx = self.subscribe_notifications();
+            let mut interval =
+                time::interval_at(Instant::now() + SLOT_POLL_INTERVAL, SLOT_POLL_INTERVAL);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                if self.concurrency().await?.available > 0 {
+                    return Ok(());
+                }
+                loop {
+                    select! {
+                        _ = interval.tick() => break,
+                        r = rx.recv() => match r {
+                            Ok(not) if matches!(not.event, Event::Start | Event::BtComplete) => {}
+                            Ok(_) | Err(RecvError::Lagged(_)) => break,
+                            Err(RecvError::Closed) => return Err(Error::Disconnected),
+                        },
+                    }
+                }
+            }
+        };
+        match timeout {
+            Some(timeout) => time::timeout(timeout, wait)
+                .await
+                .context(error::TimeoutSnafu)??,
+            None => wait.await?,
+        }
+        self.add_uri(uris, options, None, hooks).await
+    }
+
+    /// Measure the round-trip time of a `getVersion` call.
+    ///
+    /// Returns `Error::Timeout` if aria2 doesn't respond within the default timeout.
+    pub async fn ping_latency(&self) -> Result<Duration> {
+        let start = Instant::now();
+        self.get_version().await?;
+        Ok(start.elapsed())
+    }
+
+    /// Get the `max-concurrent-downloads` limit and the number of active downloads.
+    ///
+    /// The option and the stat are queried separately,
+    /// so the result may be inconsistent if they change in between.
+    pub async fn concurrency(&self) -> Result<response::ConcurrencyInfo> {
+        let max = self
+            .get_global_option()
+            .await?
+            .max_concurrent_downloads
+            // The default of aria2.
+            .unwrap_or(5);
+        let active = self.get_global_stat().await?.num_active;
+        Ok(response::ConcurrencyInfo {
+            max,
+            active,
+            available: max.saturating_sub(active),
+        })
+    }
+
+    /// Pause active tasks matching `pred` in a single `system.multicall` request,
+    /// and return gids of paused tasks.
+    ///
+    /// Tasks failed to pause, e.g. stopped in between, are logged and skipped.
+    pub async fn pause_where(
+        &self,
+        pred: impl Fn(&response::Status) -> bool,
+    ) -> Result<Vec<String>> {
+        let tasks = self.tell_active().await?;
+        self.call_for_each("pause", tasks, pred).await
+    }
+
+    /// Unpause paused tasks matching `pred` in a single `system.multicall` request,
+    /// and return gids of unpaused tasks.
+    ///
+    /// Tasks failed to unpause are logged and skipped.
+    pub async fn unpause_where(
+        &self,
+        pred: impl Fn(&response::Status) -> bool,
+    ) -> Result<Vec<String>> {
+        let num = saturating_i32(self.get_global_stat().await?.num_waiting);
+        let tasks = self.tell_waiting(0, num).await?;
+        self.call_for_each("unpause", tasks, |status| {
+            status.status == TaskStatus::Paused && pred(status)
+        })
+        .await
+    }
+
+    /// Call `method` with the gid of each task matching `pred`, and return gids of succeeded calls.
+    async fn call_for_each(
+        &self,
+        method: &str,
+        tasks: Vec<response::Status>,
+        pred: impl Fn(&response::Status) -> bool,
+    ) -> Result<Vec<String>> {
+        let gids: Vec<String> = tasks
+            .into_iter()
+            .filter(|status| pred(status))
+            .map(|status| status.gid.into_string())
+            .collect();
+        if gids.is_empty() {
+            return Ok(gids);
+        }
+        let calls = gids
+            .iter()
+            .map(|gid| (method, vec![Value::String(gid.clone())]))
+            .collect();
+        let results = self
+            .0
+            .multicall(calls, Some(self.0.extended_timeout))
+            .await?;
+        Ok(gids
+            .into_iter()
+            .zip(results)
+            .filter_map(|(gid, res)| match res {
+                Ok(_) => Some(gid),
+                Err(err) => {
+                    info!("aria2: failed to {} {}: {}", method, gid, err);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Get peers of a BitTorrent download.
+    ///
+    /// aria2 returns an empty list for other downloads or fails with a generic error,
+    /// so the task is checked to be a torrent first.
+    /// If it's not, `Error::NotATorrent` is returned,
+    /// or an empty list if `empty_if_not_torrent` is `true`.
+    pub async fn get_torrent_peers(
+        &self,
+        gid: &str,
+        empty_if_not_torrent: bool,
+    ) -> Result<Vec<response::Peer>> {
+        let status = self
+            .custom_tell_status(gid, Some(vec!["bittorrent".to_string()]))
+            .await?;
+        if !status.contains_key("bittorrent") {
+            if empty_if_not_torrent {
+                return Ok(Vec::new());
+            }
+            return error::NotATorrentSnafu { gid }.fail();
+        }
+        self.get_peers(gid).await
+    }
+
+    /// Check whether the overall download speed exceeds `target_bps` bytes/sec.
+    pub async fn should_throttle(&self, target_bps: u64) -> Result<bool> {
+        Ok(self.get_global_stat().await?.download_speed > target_bps)
+    }
+
+    /// Split `target_bps` bytes/sec among active tasks by setting their `max-download-limit`,
+    /// and return gids of tasks with the applied limits.
+    ///
+    /// The limits are changed in a single `system.multicall` request.
+    /// Tasks failed to change are logged and skipped.
+    ///
+    /// Tasks started later are not limited,
+    /// so call this again on changes, or use `max-overall-download-limit` instead
+    /// if the total speed is all that matters.
+    pub async fn share_bandwidth(
+        &self,
+        target_bps: u64,
+        share: SpeedShare,
+    ) -> Result<Vec<(String, u64)>> {
+        let tasks = self.tell_active().await?;
+        if tasks.is_empty() {
+            return Ok(Vec::new());
+        }
+        let speeds: Vec<u64> = tasks.iter().map(|t| t.download_speed).collect();
+        let limits = split_speed(target_bps, &speeds, share);
+        let calls = tasks
+            .iter()
+            .zip(&limits)
+            .map(|(task, limit)| {
+                let options = json!({ "max-download-limit": limit.to_string() });
+                (
+                    "changeOption",
+                    vec![Value::String(task.gid.to_string()), options],
+                )
+            })
+            .collect();
+        let results = self.0.multicall(calls, None).await?;
+        Ok(tasks
+            .into_iter()
+            .zip(limits)
+            .zip(results)
+            .filter_map(|((task, limit), res)| match res {
+                Ok(_) => Some((task.gid.into_string(), limit)),
+                Err(err) => {
+                    info!("aria2: failed to limit {}: {}", task.gid, err);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Add downloads at given positions of the waiting queue, and return their gids.
+    ///
+    /// Every insert shifts the tasks after it,
+    /// so items are added in the order of their positions,
+    /// and end up at the positions given once all of them are added.
+    /// Positions beyond the end of the queue are appended in order.
+    ///
+    /// If adding an item fails, items added before are kept.
+    pub async fn add_uris_ordered(&self, items: Vec<OrderedUri>) -> Result<Vec<String>> {
+        let positions: Vec<u32> = items.iter().map(|item| item.position).collect();
+        let mut items: Vec<Option<OrderedUri>> = items.into_iter().map(Some).collect();
+        let mut gids = vec![String::new(); items.len()];
+        for (i, position) in insert_positions(&positions) {
+            let item = items[i].take().expect("each item is added once");
+            gids[i] = self
+                .add_uri(item.uris, item.options, Some(position), None)
+                .await?;
+        }
+        Ok(gids)
+    }
+
+    /// Remove results of stopped tasks in a single `system.multicall` request,
+    /// and return the result for each gid in the same order.
+    pub async fn remove_download_results(&self, gids: &[&str]) -> Result<Vec<Result<()>>> {
+        if gids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let calls = gids
+            .iter()
+            .map(|gid| ("removeDownloadResult", vec![Value::String(gid.to_string())]))
+            .collect();
+        let results = self.0.multicall(calls, None).await?;
+        Ok(results.into_iter().map(|res| res.map(|_| ())).collect())
+    }
+
+    /// Remove completed tasks from the stopped list, and return the number of removed tasks.
+    ///
+    /// Unlike `purge_download_result`, results of errored and removed tasks are kept.
+    pub async fn remove_completed(&self) -> Result<usize> {
+        let num = saturating_i32(self.get_global_stat().await?.num_stopped);
+        let stopped = self
+            .custom_tell_stopped(0, num, Some(vec!["gid".to_string(), "status".to_string()]))
+            .await?;
+        let mut count = 0;
+        for map in stopped {
+            if map.get("status").and_then(|v| v.as_str()) != Some("complete") {
+                continue;
+            }
+            if let Some(gid) = map.get("gid").and_then(|v| v.as_str()) {
+                self.remove_download_result(gid).await?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Get statuses of the task and all tasks following it, in breadth-first order.
+    ///
+    /// For example, a magnet link creates a task downloading the metadata,
+    /// which is followed by the task downloading the actual data.
+    ///
+    /// Tasks that are not found (e.g. purged from the stopped list) are skipped.
+    pub async fn full_download_chain(&self, gid: &str) -> Result<Vec<response::Status>> {
+        let mut chain = Vec::new();
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::from([gid.to_string()]);
+        while let Some(gid) = queue.pop_front() {
+            // Guard against cycles.
+            if !visited.insert(gid.clone()) {
+                continue;
+            }
+            let status = match self.tell_status(&gid).await {
+                Ok(status) => status,
+                Err(Error::GidNotFound { .. }) => continue,
+                Err(err) => return Err(err),
+            };
+            if let Some(ref followed_by) = status.followed_by {
+                queue.extend(followed_by.iter().map(|gid| gid.to_string()));
+            }
+            chain.push(status);
+        }
+        Ok(chain)
+    }
+
+    /// Get all stopped tasks by paging through `tell_stopped`.
+    ///
+    /// `num_stopped` of `get_global_stat` is used as the bound,
+    /// since results beyond it (counted by `num_stopped_total`) are discarded by aria2.
+    pub async fn tell_all_stopped(&self) -> Result<Vec<response::Status>> {
+        let total = self.get_global_stat().await?.num_stopped;
+        let mut all = Vec::with_capacity(total as usize);
+        let num = saturating_i32(total);
+        let mut offset = 0;
+        while offset < num {
+            let page = self
+                .tell_stopped(offset, PAGE_SIZE.min(num - offset))
+                .await?;
+            if page.is_empty() {
+                break;
+            }
+            offset += page.len() as i32;
+            all.extend(page);
+        }
+        Ok(all)
+    }
+
+    /// Same as `tell_waiting`, but also return the total number of waiting tasks.
+    ///
+    /// Both are queried in a single `system.multicall` request,
+    /// so the count is consistent with the page.
+    pub async fn tell_waiting_with_count(
+        &self,
+        offset: i32,
+        num: i32,
+    ) -> Result<(Vec<response::Status>, usize)> {
+        let mut results = self
+            .0
+            .multicall(
+                vec![
+                    ("tellWaiting", vec![json!(offset), json!(num)]),
+                    ("getGlobalStat", vec![]),
+                ],
+                None,
+            )
+            .await?
+            .into_iter();
+        let mut next = || {
+            results.next().unwrap_or_else(|| {
+                error::UnexpectedMessageSnafu {
+                    message: "missing result of system.multicall",
+                }
+                .fail()
+            })
+        };
+        let page: Vec<response::Status> =
+            serde_json::from_value(next()?).context(error::JsonSnafu)?;
+        let stat: response::GlobalStat =
+            serde_json::from_value(next()?).context(error::JsonSnafu)?;
+        Ok((page, stat.num_waiting as usize))
+    }
+
+    /// Check whether adding `uri` to `dir` would write to a file already used by
+    /// an active, waiting or stopped task.
+    ///
+    /// The file name is guessed from the last segment of the URI path like aria2 does,
+    /// so a name given by `Content-Disposition` or the `out` option is not considered.
+    ///
+    /// Whether aria2 renames, overwrites or fails on conflict depends on
+    /// `auto_file_renaming` and `allow_overwrite` options.
+    pub async fn would_conflict(&self, uri: &str, dir: &str) -> Result<bool> {
+        let target = Path::new(dir).join(file_name_from_uri(uri));
+        Ok(self.all_file_paths().await?.contains(&target))
+    }
+
+    /// Pause the task like [`Client::pause`], and return its status before pausing.
+    ///
+    /// The status is fetched by a separate `tellStatus` call right before,
+    /// so it can be outdated if the task changes in between, e.g. a waiting task starts.
+    pub async fn pause_with_previous(&self, gid: &str) -> Result<TaskStatus> {
+        let previous = self.task_status(gid).await?;
+        self.pause(gid).await?;
+        Ok(previous)
+    }
+
+    /// Unpause the task like [`Client::unpause`], and return its status before unpausing.
+    ///
+    /// See [`Client::pause_with_previous`] for the race with the status fetch.
+    pub async fn unpause_with_previous(&self, gid: &str) -> Result<TaskStatus> {
+        let previous = self.task_status(gid).await?;
+        self.unpause(gid).await?;
+        Ok(previous)
+    }
+
+    /// Status of the task with given gid, with only the `status` key fetched.
+    async fn task_status(&self, gid: &str) -> Result<TaskStatus> {
+        let mut map = self
+            .custom_tell_status(gid, Some(vec!["status".to_string()]))
+            .await?;
+        let status = map.remove("status").unwrap_or_default();
+        serde_json::from_value(status).context(error::JsonSnafu)
+    }
+
+    /// Get given keys of all active, waiting and stopped tasks.
+    async fn tell_all_keys(&self, keys: &[&str]) -> Result<Vec<Map<String, Value>>> {
+        let stat = self.get_global_stat().await?;
+        let keys = || Some(keys.iter().map(|k| k.to_string()).collect());
+        let mut maps = self.custom_tell_active(keys()).await?;
+        maps.extend(
+            self.custom_tell_waiting(0, saturating_i32(stat.num_waiting), keys())
+                .await?,
+        );
+        maps.extend(
+            self.custom_tell_stopped(0, saturating_i32(stat.num_stopped), keys())
+                .await?,
+        );
+        Ok(maps)
+    }
+
+    /// Statuses of all active, waiting and stopped tasks.
+    async fn all_statuses(&self) -> Result<HashMap<String, TaskStatus>> {
+        let mut statuses = HashMap::new();
+        for mut map in self.tell_all_keys(&["gid", "status"]).await? {
+            if let (Some(Value::String(gid)), Some(status)) =
+                (map.remove("gid"), map.remove("status"))
+            {
+                let status = serde_json::from_value(status).context(error::JsonSnafu)?;
+                statuses.insert(gid, status);
+            }
+        }
+        Ok(statuses)
+    }
+
+    /// Paths of files of all active, waiting and stopped tasks.
+    async fn all_file_paths(&self) -> Result<Vec<PathBuf>> {
+        let maps = self.tell_all_keys(&["files"]).await?;
+
+        let mut paths = Vec::new();
+        for mut map in maps {
+            if let Some(files) = map.remove("files") {
+                let files: Vec<response::File> =
+                    serde_json::from_value(files).context(error::JsonSnafu)?;
+                paths.extend(
+                    files
+                        .into_iter()
+                        .filter(|f| !f.path.is_empty())
+                        .map(|f| PathBuf::from(f.path)),
+                );
+            }
+        }
+        Ok(paths)
+    }
+}
+
+/// Guess the file name from an URI, like aria2 does without `Content-Disposition`.
+fn file_name_from_uri(uri: &str) -> String {
+    let path = uri.split(['?', '#']).next().unwrap_or_default();
+    let path = path.split_once("://").map_or(path, |(_, rest)| rest);
+    let name = match path.split_once('/') {
+        Some((_, path)) => path.rsplit('/').next().unwrap_or_default(),
+        None => "",
+    };
+    if name.is_empty() {
+        return "index.html".to_string();
+    }
+    percent_decode(name)
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(b) = s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{file_name_from_uri, insert_positions, split_speed, SpeedShare};
+
+    #[test]
+    fn ordered_positions() {
+        assert_eq!(insert_positions(&[2, 0, 1]), [(1, 0), (2, 1), (0, 2)]);
+        // Taken positions move to the next one.
+        assert_eq!(insert_positions(&[3, 3, 4]), [(0, 3), (1, 4), (2, 5)]);
+    }
+
+    #[test]
+    fn speed_share() {
+        assert_eq!(split_speed(100, &[10, 30], SpeedShare::Equal), [50, 50]);
+        assert_eq!(
+            split_speed(100, &[10, 30], SpeedShare::Proportional),
+            [25, 75]
+        );
+        // Idle tasks share equally.
+        assert_eq!(
+            split_speed(100, &[0, 0], SpeedShare::Proportional),
+            [50, 50]
+        );
+        // 0 would mean unrestricted.
+        assert_eq!(
+            split_speed(100, &[0, 1000], SpeedShare::Proportional),
+            [1, 100]
+        );
+    }
+
+    #[test]
+    fn file_name() {
+        assert_eq!(
+            file_name_from_uri("https://example.com/a/b%20c.iso?x=1#y"),
+            "b c.iso"
+        );
+        assert_eq!(file_name_from_uri("https://example.com/"), "index.html");
+        assert_eq!(file_name_from_uri("https://example.com"), "index.html");
+    }
+}
@@ -47,8 +47,8 @@
 //!             ],
 //!             Some(options.clone()),
 //!             None,
-//!             Some(TaskHooks {
-//!                 on_complete: Some({
+//!             Some(TaskHooks::new(
+//!                 Some({
 //!                     let s = semaphore.clone();
 //!                     async move {
 //!                         s.add_permits(1);
@@ -56,7 +56,7 @@
 //!                     }
 //!                     .boxed()
 //!                 }),
-//!                 on_error: Some({
+//!                 Some({
 //!                     let s = semaphore.clone();
 //!                     async move {
 //!                         s.add_permits(1);
@@ -64,7 +64,7 @@
 //!                     }
 //!                     .boxed()
 //!                 }),
-//!             }),
+//!             )),
 //!         )
 //!         .await
 //!         .unwrap();
@@ -78,8 +78,8 @@
 //!             ],
 //!             Some(options.clone()),
 //!             None,
-//!             Some(TaskHooks {
-//!                 on_complete: Some({
+//!             Some(TaskHooks::new(
+//!                 Some({
 //!                     let s = semaphore.clone();
 //!                     async move {
 //!                         s.add_permits(1);
@@ -87,7 +87,7 @@
 //!                     }
 //!                     .boxed()
 //!                 }),
-//!                 on_error: Some({
+//!                 Some({
 //!                     let s = semaphore.clone();
 //!                     async move {
 //!                         s.add_permits(1);
@@ -95,7 +95,7 @@
 //!                     }
 //!                     .boxed()
 //!                 }),
-//!             }),
+//!             )),
 //!         )
 //!         .await
 //!         .unwrap();
@@ -116,13 +116,23 @@
 //!
 //! ```
 
+#![allow(clippy::result_large_err)]
+
+mod api;
 mod client;
+mod download;
 mod error;
+mod helper;
 mod method;
 pub mod options;
 pub mod response;
 mod utils;
-pub use options::TaskOptions;
+pub use api::Aria2Api;
+pub use client::{ClientConfig, IdStrategy, MethodCall, ReconnectConfig};
+pub use download::Download;
+pub use helper::{cancellable, OrderedUri, SpeedHistory, SpeedShare};
+pub use method::PositionHow;
+pub use options::{GlobalOptions, TaskOptions};
 
 pub use error::Error;
 
@@ -131,17 +141,17 @@ pub use serde_json::Map;
 
 use std::collections::{HashMap, HashSet};
 
-use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicBool, AtomicU64};
 use std::sync::{Arc, Mutex};
 
 use std::time::Duration;
 
-use futures::future::BoxFuture;
+use futures::{future::BoxFuture, Future, FutureExt};
 use response::Event;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use tokio::sync::{broadcast, Notify};
+use tokio::sync::{broadcast, watch, Notify};
 
 use tokio::sync::{mpsc, oneshot};
 use tokio_tungstenite::tungstenite::Message;
@@ -149,16 +159,21 @@ use tokio_tungstenite::tungstenite::Message;
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RpcRequest {
     pub id: Option<u64>,
+    #[serde(default)]
     pub jsonrpc: String,
     pub method: String,
-    #[serde(default)]
+    // Some aria2-compatible servers send `null` instead of omitting it.
+    #[serde(default, deserialize_with = "utils::null_as_default")]
     pub params: Vec<Value>,
 }
 
 /// Error returned by RPC calls.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Aria2Error {
+    // XML-RPC style names are accepted for `system.multicall` results.
+    #[serde(alias = "faultCode")]
     pub code: i32,
+    #[serde(alias = "faultString")]
     pub message: String,
 }
 impl std::fmt::Display for Aria2Error {
@@ -182,36 +197,134 @@ pub struct RpcResponse {
 
 /// Hooks that will be executed on notifications.
 ///
-/// If the connection lost, all hooks will be checked whether they need to be executed once reconnected.
+/// Each event can have multiple callbacks.
+/// They are spawned as separate tasks when the event fires, so they run concurrently.
+///
+/// Hooks are stored by gid in the client and are kept across reconnects.
+///
+/// Notifications sent while the connection is lost are missed,
+/// so once reconnected, all stopped tasks are checked
+/// and hooks of tasks completed, errored or removed in the meantime will be executed.
+///
+/// `on_stop` is executed when the task is stopped by the user, e.g. by `remove`,
+/// so it can be told apart from `on_error`.
+/// `on_bt_complete` is executed when a torrent is downloaded and seeding starts,
+/// while `on_complete` is executed when seeding stops.
+/// It's never executed for other downloads, and is not recovered after reconnected.
+///
+/// Once the task has stopped, completed or errored, the remaining hooks are dropped.
 #[derive(Default)]
 pub struct TaskHooks {
-    // pub on_start: Option<BoxFuture<'static, ()>>,
-    // pub on_pause: Option<BoxFuture<'static, ()>>,
-    // pub on_stop: Option<BoxFuture<'static, ()>>,
-    pub on_complete: Option<BoxFuture<'static, ()>>,
-    pub on_error: Option<BoxFuture<'static, ()>>,
-    // pub on_bt_complete: Option<BoxFuture<'static, ()>>,
+    pub on_start: Vec<BoxFuture<'static, ()>>,
+    pub on_stop: Vec<BoxFuture<'static, ()>>,
+    /// For torrents, executed when seeding stops rather than when the download finishes,
+    /// so never executed if seeding doesn't stop by `seed-time` or `seed-ratio`.
+    /// Use `on_bt_complete` to be notified as soon as a torrent is downloaded.
+    pub on_complete: Vec<BoxFuture<'static, ()>>,
+    pub on_error: Vec<BoxFuture<'static, ()>>,
+    pub on_bt_complete: Vec<BoxFuture<'static, ()>>,
 }
 
 impl TaskHooks {
+    /// Create hooks with at most one callback for each event.
+    pub fn new(
+        on_complete: Option<BoxFuture<'static, ()>>,
+        on_error: Option<BoxFuture<'static, ()>>,
+    ) -> Self {
+        Self {
+            on_complete: on_complete.into_iter().collect(),
+            on_error: on_error.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Add a callback executed when the task starts.
+    ///
+    /// Each callback is executed once, even if the task is started again after paused.
+    pub fn with_on_start(mut self, hook: impl Future<Output = ()> + Send + 'static) -> Self {
+        self.on_start.push(hook.boxed());
+        self
+    }
+
+    /// Add a callback executed when the task is stopped by the user.
+    pub fn with_on_stop(mut self, hook: impl Future<Output = ()> + Send + 'static) -> Self {
+        self.on_stop.push(hook.boxed());
+        self
+    }
+
+    /// Add a callback executed when the task completes.
+    pub fn with_on_complete(mut self, hook: impl Future<Output = ()> + Send + 'static) -> Self {
+        self.on_complete.push(hook.boxed());
+        self
+    }
+
+    /// Add a callback executed when the task stops due to an error.
+    pub fn with_on_error(mut self, hook: impl Future<Output = ()> + Send + 'static) -> Self {
+        self.on_error.push(hook.boxed());
+        self
+    }
+
+    /// Add a callback executed when the torrent is downloaded and seeding starts.
+    pub fn with_on_bt_complete(mut self, hook: impl Future<Output = ()> + Send + 'static) -> Self {
+        self.on_bt_complete.push(hook.boxed());
+        self
+    }
+
     pub fn is_some(&self) -> bool {
-        self.on_complete.is_some() || self.on_error.is_some()
+        !self.on_start.is_empty()
+            || !self.on_stop.is_empty()
+            || !self.on_complete.is_empty()
+            || !self.on_error.is_empty()
+            || !self.on_bt_complete.is_empty()
     }
 }
 
 type Hooks = Arc<Mutex<(HashMap<String, TaskHooks>, HashMap<String, HashSet<Event>>)>>;
 
+/// A call waiting for the response with the same id.
+struct Subscription {
+    // An error is sent if the call is cancelled or disconnected.
+    tx: oneshot::Sender<Result<RpcResponse, Error>>,
+    // The gid the call operates on, if any.
+    gid: Option<String>,
+}
+
+type Subscriptions = Arc<Mutex<HashMap<u64, Subscription>>>;
+
 struct InnerClient {
+    label: Option<String>,
+    // Prefix of logs, including the label.
+    log_name: String,
     token: Option<String>,
     tx_write: mpsc::Sender<Message>,
     id: AtomicU64,
-    subscriptions: Arc<Mutex<HashMap<u64, oneshot::Sender<RpcResponse>>>>,
+    id_strategy: IdStrategy,
+    subscriptions: Subscriptions,
     shutdown: Arc<Notify>,
+    // Notify the worker to re-establish the connection, `None` if it can't.
+    reconnect: Option<Arc<Notify>>,
+    // The number of connections established.
+    connected: watch::Receiver<u64>,
     // hooks and pending events
     hooks: Hooks,
     default_timeout: Duration,
     extended_timeout: Duration,
     tx_not: broadcast::Sender<response::Notification>,
+    tx_raw_not: broadcast::Sender<RpcRequest>,
+    dropped_notifications: Arc<AtomicU64>,
+    // Counted from notifications of the current connection.
+    session: Arc<client::SessionCounters>,
+    // aria2 version cached from the last `getVersion` call.
+    // Cleared on reconnect since the server may have been upgraded.
+    version: Mutex<Option<response::SemVer>>,
+    // Cached by `Client::server_info`, and cleared on reconnect like `version`.
+    server_info: Mutex<Option<response::ServerInfo>>,
+    // Set once `system.multicall` is found unsupported, and cleared on reconnect.
+    multicall_unsupported: AtomicBool,
+    // Options applied to all tasks added by this client.
+    default_options: Mutex<Option<TaskOptions>>,
+    // Trackers added to torrents and magnet links added by this client.
+    default_trackers: Mutex<Vec<String>>,
 }
 
 /// An aria2 websocket rpc client.
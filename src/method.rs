@@ -1,12 +1,17 @@
-use std::time::Duration;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    time::Duration,
+};
 
 use crate::{
     error,
-    options::TaskOptions,
+    options::{self, GlobalOptions, TaskOptions},
     response,
-    utils::{value_into_vec, PushExt},
+    utils::{encode_base64, looks_like_torrent, value_into_vec, PushExt},
     Client, Error, InnerClient, TaskHooks,
 };
+use log::info;
 use serde::Serialize;
 use serde_json::{json, to_value, Map, Value};
 use snafu::prelude::*;
@@ -14,6 +19,25 @@ use snafu::prelude::*;
 type Result<T> = std::result::Result<T, Error>;
 
 impl InnerClient {
+    pub(crate) async fn server_info(&self) -> Result<response::ServerInfo> {
+        if let Some(ref info) = *self.server_info.lock().unwrap() {
+            return Ok(info.clone());
+        }
+        let version: response::Version =
+            self.call_and_subscribe("getVersion", vec![], None).await?;
+        let session: response::SessionInfo = self
+            .call_and_subscribe("getSessionInfo", vec![], None)
+            .await?;
+        let info = response::ServerInfo {
+            version: version.version,
+            enabled_features: version.enabled_features,
+            session_id: session.session_id,
+        };
+        *self.version.lock().unwrap() = Some(info.semver());
+        *self.server_info.lock().unwrap() = Some(info.clone());
+        Ok(info)
+    }
+
     async fn custom_tell_multi(
         &self,
         method: &str,
@@ -37,6 +61,33 @@ impl InnerClient {
     }
 }
 
+/// Keys of `response::Status`, except `files`.
+const STATUS_KEYS_WITHOUT_FILES: &[&str] = &[
+    "gid",
+    "status",
+    "totalLength",
+    "completedLength",
+    "uploadLength",
+    "bitfield",
+    "downloadSpeed",
+    "uploadSpeed",
+    "infoHash",
+    "numSeeders",
+    "seeder",
+    "pieceLength",
+    "numPieces",
+    "connections",
+    "errorCode",
+    "errorMessage",
+    "followedBy",
+    "following",
+    "belongsTo",
+    "dir",
+    "bittorrent",
+    "verifiedLength",
+    "verifyIntegrityPending",
+];
+
 /// The parameter `how` in `changePosition`.
 ///
 /// <https://aria2.github.io/manual/en/html/aria2c.html#aria2.changePosition>
@@ -50,16 +101,95 @@ pub enum PositionHow {
 
 impl Client {
     pub async fn get_version(&self) -> Result<response::Version> {
-        self.call_and_subscribe("getVersion", vec![], None).await
+        let version: response::Version =
+            self.call_and_subscribe("getVersion", vec![], None).await?;
+        *self.0.version.lock().unwrap() = Some(version.semver());
+        Ok(version)
+    }
+
+    /// Check whether the version of aria2 is at least `major.minor.patch`.
+    ///
+    /// The version is cached after the first `getVersion` call,
+    /// so only the first call will query aria2.
+    pub async fn version_at_least(&self, major: u32, minor: u32, patch: u32) -> Result<bool> {
+        let cached = *self.0.version.lock().unwrap();
+        let version = match cached {
+            Some(v) => v,
+            None => self.get_version().await?.semver(),
+        };
+        Ok(version >= response::SemVer::new(major, minor, patch))
+    }
+
+    /// Get the version, enabled features and session id of aria2.
+    ///
+    /// The result is cached until the connection is re-established,
+    /// so only the first call will query aria2.
+    /// Set `ClientConfig::fetch_server_info` to query it as soon as connected.
+    pub async fn server_info(&self) -> Result<response::ServerInfo> {
+        self.0.server_info().await
+    }
+
+    /// Get the cached result of [`Client::server_info`], without querying aria2.
+    pub fn cached_server_info(&self) -> Option<response::ServerInfo> {
+        self.0.server_info.lock().unwrap().clone()
+    }
+
+    /// Set options applied to all tasks added by `add_uri`, `add_torrent` and `add_metalink`.
+    ///
+    /// Options passed to each call are merged over the default options.
+    ///
+    /// The default options are shared by all clones of this client.
+    pub fn with_default_options(self, options: TaskOptions) -> Self {
+        *self.0.default_options.lock().unwrap() = Some(options);
+        self
+    }
+
+    fn apply_default_options(&self, options: Option<TaskOptions>) -> Result<Option<TaskOptions>> {
+        let defaults = self.0.default_options.lock().unwrap().clone();
+        Ok(match (defaults, options) {
+            (Some(defaults), Some(options)) => Some(defaults.merge(&options)?),
+            (defaults, options) => options.or(defaults),
+        })
+    }
+
+    /// Set trackers appended to the `bt-tracker` option of tasks added by
+    /// `add_torrent`, or `add_uri` with magnet links.
+    ///
+    /// Trackers already given in the options are not duplicated.
+    /// Torrents downloaded by HTTP(S) URIs are not affected.
+    ///
+    /// The default trackers are shared by all clones of this client.
+    pub fn with_default_trackers(self, trackers: Vec<String>) -> Self {
+        *self.0.default_trackers.lock().unwrap() = trackers;
+        self
+    }
+
+    fn apply_default_trackers(&self, options: Option<TaskOptions>) -> Option<TaskOptions> {
+        let defaults = self.0.default_trackers.lock().unwrap().clone();
+        if defaults.is_empty() {
+            return options;
+        }
+        let mut options = options.unwrap_or_default();
+        let trackers = options.bt_tracker.get_or_insert_with(Vec::new);
+        for tracker in defaults {
+            if !trackers.contains(&tracker) {
+                trackers.push(tracker);
+            }
+        }
+        Some(options)
     }
 
     pub async fn add_uri(
         &self,
         uris: Vec<String>,
-        options: Option<TaskOptions>,
+        options: impl Into<Option<TaskOptions>>,
         position: Option<u32>,
         hooks: Option<TaskHooks>,
     ) -> Result<String> {
+        let mut options = self.apply_default_options(options.into())?;
+        if uris.iter().any(|uri| uri.starts_with("magnet:")) {
+            options = self.apply_default_trackers(options);
+        }
         let mut params = vec![to_value(uris).context(error::JsonSnafu)?];
         params.push_else(options, json!({}))?;
         params.push_some(position)?;
@@ -69,17 +199,40 @@ impl Client {
         Ok(gid)
     }
 
+    /// Add a BitTorrent download from the content of a torrent file.
+    ///
+    /// Returns `Error::InvalidTorrent` without sending the request
+    /// if the content is not a bencoded dictionary with an `info` dictionary.
+    /// Use `add_torrent_unchecked` to leave all validation to aria2.
     pub async fn add_torrent(
         &self,
         torrent: impl AsRef<[u8]>,
         uris: Option<Vec<String>>,
-        options: Option<TaskOptions>,
+        options: impl Into<Option<TaskOptions>>,
+        position: Option<u32>,
+        hooks: Option<TaskHooks>,
+    ) -> Result<String> {
+        ensure!(
+            looks_like_torrent(torrent.as_ref()),
+            error::InvalidTorrentSnafu
+        );
+        self.add_torrent_unchecked(torrent, uris, options, position, hooks)
+            .await
+    }
+
+    /// Same as `add_torrent`, but the content is sent to aria2 without checking.
+    pub async fn add_torrent_unchecked(
+        &self,
+        torrent: impl AsRef<[u8]>,
+        uris: Option<Vec<String>>,
+        options: impl Into<Option<TaskOptions>>,
         position: Option<u32>,
         hooks: Option<TaskHooks>,
     ) -> Result<String> {
-        let mut params = vec![Value::String(base64::encode(torrent))];
+        let mut params = vec![Value::String(encode_base64(torrent.as_ref()))];
         params.push_else(uris, json!([]))?;
-        params.push_else(options, json!({}))?;
+        let options = self.apply_default_options(options.into())?;
+        params.push_else(self.apply_default_trackers(options), json!({}))?;
         params.push_some(position)?;
 
         let gid: String = self.call_and_subscribe("addTorrent", params, None).await?;
@@ -90,12 +243,12 @@ impl Client {
     pub async fn add_metalink(
         &self,
         metalink: impl AsRef<[u8]>,
-        options: Option<TaskOptions>,
+        options: impl Into<Option<TaskOptions>>,
         position: Option<u32>,
         hooks: Option<TaskHooks>,
     ) -> Result<String> {
-        let mut params = vec![Value::String(base64::encode(metalink))];
-        params.push_else(options, json!({}))?;
+        let mut params = vec![Value::String(encode_base64(metalink.as_ref()))];
+        params.push_else(self.apply_default_options(options.into())?, json!({}))?;
         params.push_some(position)?;
 
         let gid: String = self.call_and_subscribe("addMetalink", params, None).await?;
@@ -104,7 +257,7 @@ impl Client {
     }
 
     async fn do_gid(&self, method: &str, gid: &str, timeout: Option<Duration>) -> Result<()> {
-        self.call_and_subscribe::<String>(method, vec![Value::String(gid.to_string())], timeout)
+        self.call_for_gid::<String>(gid, method, vec![Value::String(gid.to_string())], timeout)
             .await?;
         Ok(())
     }
@@ -118,6 +271,19 @@ impl Client {
         self.do_gid("forceRemove", gid, None).await
     }
 
+    /// Same as `remove`, or `force_remove` if `force` is `true`,
+    /// but log the reason at info level under the `aria2_ws::remove` target.
+    ///
+    /// aria2 doesn't store the reason. It's only for auditing removals on the client side.
+    pub async fn remove_with_reason(&self, gid: &str, reason: &str, force: bool) -> Result<()> {
+        info!(target: "aria2_ws::remove", "removing {} (force: {}): {}", gid, force, reason);
+        if force {
+            self.force_remove(gid).await
+        } else {
+            self.remove(gid).await
+        }
+    }
+
     pub async fn pause(&self, gid: &str) -> Result<()> {
         self.do_gid("pause", gid, Some(self.0.extended_timeout))
             .await
@@ -156,32 +322,54 @@ impl Client {
     ) -> Result<Map<String, Value>> {
         let mut params = vec![Value::String(gid.to_string())];
         params.push_some(keys)?;
-        self.call_and_subscribe("tellStatus", params, None).await
+        self.call_for_gid(gid, "tellStatus", params, None).await
     }
 
     pub async fn tell_status(&self, gid: &str) -> Result<response::Status> {
-        self.call_and_subscribe("tellStatus", vec![Value::String(gid.to_string())], None)
-            .await
+        self.call_for_gid(
+            gid,
+            "tellStatus",
+            vec![Value::String(gid.to_string())],
+            None,
+        )
+        .await
+    }
+
+    /// Same as `tell_status`, but `files` is not requested and left empty.
+    ///
+    /// The list of files can be huge for torrents with many files.
+    pub async fn tell_status_without_files(&self, gid: &str) -> Result<response::Status> {
+        let keys: Vec<String> = STATUS_KEYS_WITHOUT_FILES
+            .iter()
+            .map(|k| k.to_string())
+            .collect();
+        let params = value_into_vec(json!([gid, keys]));
+        self.call_for_gid(gid, "tellStatus", params, None).await
     }
 
     pub async fn get_uris(&self, gid: &str) -> Result<Vec<response::Uri>> {
-        self.call_and_subscribe("getUris", vec![Value::String(gid.to_string())], None)
+        self.call_for_gid(gid, "getUris", vec![Value::String(gid.to_string())], None)
             .await
     }
 
     pub async fn get_files(&self, gid: &str) -> Result<Vec<response::File>> {
-        self.call_and_subscribe("getFiles", vec![Value::String(gid.to_string())], None)
+        self.call_for_gid(gid, "getFiles", vec![Value::String(gid.to_string())], None)
             .await
     }
 
     pub async fn get_peers(&self, gid: &str) -> Result<Vec<response::Peer>> {
-        self.call_and_subscribe("getPeers", vec![Value::String(gid.to_string())], None)
+        self.call_for_gid(gid, "getPeers", vec![Value::String(gid.to_string())], None)
             .await
     }
 
     pub async fn get_servers(&self, gid: &str) -> Result<Vec<response::GetServersResult>> {
-        self.call_and_subscribe("getServers", vec![Value::String(gid.to_string())], None)
-            .await
+        self.call_for_gid(
+            gid,
+            "getServers",
+            vec![Value::String(gid.to_string())],
+            None,
+        )
+        .await
     }
 
     pub async fn tell_active(&self) -> Result<Vec<response::Status>> {
@@ -229,8 +417,7 @@ impl Client {
 
     pub async fn change_position(&self, gid: &str, pos: i32, how: PositionHow) -> Result<i32> {
         let params = value_into_vec(json!([gid, pos, how]));
-        self.call_and_subscribe("changePosition", params, None)
-            .await
+        self.call_for_gid(gid, "changePosition", params, None).await
     }
 
     /// # Returns
@@ -248,37 +435,110 @@ impl Client {
     ) -> Result<(i32, i32)> {
         let mut params = value_into_vec(json!([gid, file_index, del_uris, add_uris]));
         params.push_some(position)?;
-        self.call_and_subscribe("changeUri", params, None).await
+        self.call_for_gid(gid, "changeUri", params, None).await
     }
 
     pub async fn get_option(&self, gid: &str) -> Result<TaskOptions> {
-        self.call_and_subscribe("getOption", vec![Value::String(gid.to_string())], None)
+        self.call_for_gid(gid, "getOption", vec![Value::String(gid.to_string())], None)
             .await
     }
 
+    /// Change options of the task dynamically.
+    ///
+    /// Returns `Error::OptionNotChangeable` with the first key in [`options::NOT_CHANGEABLE_OPTIONS`]
+    /// without sending the request, since aria2 would silently ignore it.
     pub async fn change_option(&self, gid: &str, options: TaskOptions) -> Result<()> {
-        self.call_and_subscribe(
+        let map = options::to_map(&options)?;
+        if let Some(key) = map.keys().find(|key| !options::is_changeable(key)) {
+            return error::OptionNotChangeableSnafu { key: key.clone() }.fail();
+        }
+        self.call_for_gid::<String>(
+            gid,
             "changeOption",
-            vec![
-                Value::String(gid.to_string()),
-                to_value(options).context(error::JsonSnafu)?,
-            ],
+            vec![Value::String(gid.to_string()), Value::Object(map)],
             None,
         )
-        .await
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_global_option(&self) -> Result<GlobalOptions> {
+        self.call_and_subscribe("getGlobalOption", vec![], None)
+            .await
     }
 
-    pub async fn get_global_option(&self) -> Result<TaskOptions> {
+    /// Get all global options as sorted key-value strings,
+    /// including options not modeled by `TaskOptions`.
+    ///
+    /// Options without a default value and not set by the user are not returned by aria2.
+    pub async fn effective_options(&self) -> Result<BTreeMap<String, String>> {
         self.call_and_subscribe("getGlobalOption", vec![], None)
             .await
     }
 
-    pub async fn change_global_option(&self, options: TaskOptions) -> Result<()> {
-        self.call_and_subscribe(
+    /// Get the value of an option of the task, or the global value if the task doesn't have it.
+    ///
+    /// Both are queried as raw strings, including options not modeled by `TaskOptions`.
+    /// Returns `None` if neither has a value.
+    pub async fn effective_option(&self, gid: &str, key: &str) -> Result<Option<String>> {
+        let mut task: BTreeMap<String, String> = self
+            .call_for_gid(gid, "getOption", vec![Value::String(gid.to_string())], None)
+            .await?;
+        if let Some(value) = task.remove(key) {
+            return Ok(Some(value));
+        }
+        Ok(self.effective_options().await?.remove(key))
+    }
+
+    pub async fn change_global_option(&self, options: GlobalOptions) -> Result<()> {
+        self.call_and_subscribe::<String>(
             "changeGlobalOption",
             vec![to_value(options).context(error::JsonSnafu)?],
             None,
         )
+        .await?;
+        Ok(())
+    }
+
+    /// Same as `change_global_option`, but only options different from the current values are sent,
+    /// and their keys are returned.
+    ///
+    /// Nothing is sent if no option is changed.
+    pub async fn change_global_option_diff(
+        &self,
+        options: GlobalOptions,
+    ) -> Result<BTreeSet<String>> {
+        let current = self.effective_options().await?;
+        let changed: Map<String, Value> = match to_value(options).context(error::JsonSnafu)? {
+            Value::Object(map) => map
+                .into_iter()
+                .filter(|(key, value)| {
+                    options::option_changed(current.get(key).map(String::as_str), value)
+                })
+                .collect(),
+            _ => unreachable!("GlobalOptions is serialized as an object"),
+        };
+        let keys = changed.keys().cloned().collect();
+        if !changed.is_empty() {
+            self.call_and_subscribe::<String>(
+                "changeGlobalOption",
+                vec![Value::Object(changed)],
+                None,
+            )
+            .await?;
+        }
+        Ok(keys)
+    }
+
+    /// Save cookies to the file at `path` on the host running aria2, by the `save-cookies` option.
+    ///
+    /// Cookies can only be loaded when aria2 starts,
+    /// so start aria2 with `--load-cookies` pointing to the same file to keep them across restarts.
+    pub async fn set_cookie_jar(&self, path: impl Into<PathBuf>) -> Result<()> {
+        self.change_global_option(GlobalOptions {
+            save_cookies: Some(path.into()),
+            ..Default::default()
+        })
         .await
     }
 
@@ -293,7 +553,8 @@ impl Client {
     }
 
     pub async fn remove_download_result(&self, gid: &str) -> Result<()> {
-        self.call_and_subscribe::<String>(
+        self.call_for_gid::<String>(
+            gid,
             "removeDownloadResult",
             vec![Value::String(gid.to_string())],
             None,
@@ -324,4 +585,15 @@ impl Client {
             .await?;
         Ok(())
     }
+
+    /// Names of all RPC methods supported by aria2, including the `aria2.` prefix,
+    /// e.g. to check whether `aria2.forcePause` exists before calling it.
+    pub async fn list_methods(&self) -> Result<Vec<String>> {
+        self.0.call_system("listMethods").await
+    }
+
+    /// Names of all notifications supported by aria2, e.g. `aria2.onDownloadStart`.
+    pub async fn list_notifications(&self) -> Result<Vec<String>> {
+        self.0.call_system("listNotifications").await
+    }
 }
@@ -1,6 +1,19 @@
+use std::{collections::HashMap, fmt, path::PathBuf, str::FromStr, time::Duration};
+
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
-use serde_with::{serde_as, skip_serializing_none, DisplayFromStr};
+use serde_with::{
+    serde_as, skip_serializing_none, CommaSeparator, DisplayFromStr, DurationSeconds,
+    StringWithSeparator,
+};
+
+use snafu::ResultExt;
+
+use crate::{
+    error,
+    utils::{is_none_or_empty, DurationMinutes},
+    Error,
+};
 
 /// Regular options of aria2 download tasks.
 ///
@@ -12,6 +25,7 @@ use serde_with::{serde_as, skip_serializing_none, DisplayFromStr};
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 #[serde(rename_all = "kebab-case")]
 pub struct TaskOptions {
+    #[serde(default, skip_serializing_if = "is_none_or_empty")]
     pub header: Option<Vec<String>>,
 
     #[serde_as(as = "Option<DisplayFromStr>")]
@@ -22,6 +36,12 @@ pub struct TaskOptions {
 
     pub dir: Option<String>,
 
+    /// The file name of the downloaded file.
+    ///
+    /// It is always relative to the directory given in `dir` option.
+    ///
+    /// For BitTorrent and Metalink downloads with multiple files, this option is ignored.
+    /// Use `index-out` in `extra_options` to rename each file instead.
     pub out: Option<String>,
 
     pub gid: Option<String>,
@@ -30,14 +50,39 @@ pub struct TaskOptions {
     #[serde(default)]
     pub r#continue: Option<bool>,
 
+    /// Rename file name if the same file already exists.
+    ///
+    /// aria2 appends a dot and a number from 1 to 9999 to the file name, e.g. `file.1.txt`.
+    ///
+    /// This option works only in HTTP(S)/FTP download.
+    ///
+    /// Default: true
     #[serde_as(as = "Option<DisplayFromStr>")]
     #[serde(default)]
     pub auto_file_renaming: Option<bool>,
 
+    /// Restart download from scratch if the corresponding control file doesn't exist.
+    ///
+    /// See also `auto_file_renaming`.
+    ///
+    /// Default: false
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub allow_overwrite: Option<bool>,
+
     #[serde_as(as = "Option<DisplayFromStr>")]
     #[serde(default)]
     pub check_integrity: Option<bool>,
 
+    /// Add the download in the paused state, the `pause` option of aria2.
+    ///
+    /// Paused downloads can be released by `unpause` or `unpause_all`.
+    ///
+    /// Only available when adding a download.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default, rename = "pause")]
+    pub pause_on_add: Option<bool>,
+
     /// Close connection if download speed is lower than or equal to this value(bytes per sec).
     ///
     /// 0 means aria2 does not have a lowest speed limit.
@@ -62,14 +107,728 @@ pub struct TaskOptions {
     #[serde(default)]
     pub max_connection_per_server: Option<i32>,
 
+    /// Options about retrying failed connections.
+    #[serde(flatten)]
+    pub retry: RetryPolicy,
+
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub timeout: Option<i32>,
+
+    /// Set file to download by specifying its index, e.g. `1,3-5`.
+    ///
+    /// The index starts from 1 and can be found by `get_files`.
+    ///
+    /// This option is only available for multi-file downloads like BitTorrent and Metalink.
+    pub select_file: Option<String>,
+
+    /// Allow to resume a download even if the piece length differs from the control file.
+    ///
+    /// Otherwise aria2 aborts the download.
+    /// Progress of the download is lost if it's resumed this way.
+    ///
+    /// Default: false
     #[serde_as(as = "Option<DisplayFromStr>")]
     #[serde(default)]
-    pub max_tries: Option<i32>,
+    pub allow_piece_length_change: Option<bool>,
+
+    /// Additional BitTorrent tracker announce URIs.
+    ///
+    /// They are not affected by `bt_exclude_tracker`,
+    /// since they are added after URIs there are removed.
+    #[serde_as(as = "Option<StringWithSeparator<CommaSeparator, String>>")]
+    #[serde(default, skip_serializing_if = "is_none_or_empty")]
+    pub bt_tracker: Option<Vec<String>>,
 
+    /// BitTorrent tracker announce URIs to remove.
+    ///
+    /// Use `*` to remove all URIs from the torrent file, e.g. to only use `bt_tracker`.
+    #[serde_as(as = "Option<StringWithSeparator<CommaSeparator, String>>")]
+    #[serde(default, skip_serializing_if = "is_none_or_empty")]
+    pub bt_exclude_tracker: Option<Vec<String>>,
+
+    /// Stop seeding when share ratio reaches this value.
+    ///
+    /// Specifying 0.0 makes aria2 seed regardless of the share ratio.
+    ///
+    /// If `seed_time` is also given, seeding ends when at least one of the conditions is satisfied.
+    ///
+    /// Default: 1.0
     #[serde_as(as = "Option<DisplayFromStr>")]
     #[serde(default)]
-    pub timeout: Option<i32>,
+    pub seed_ratio: Option<f64>,
+
+    /// Stop seeding after this time elapsed.
+    ///
+    /// aria2 takes this value in (fractional) minutes,
+    /// so it will be rounded to the precision of a float number of minutes.
+    ///
+    /// Specifying `Duration::ZERO` disables seeding after download completed.
+    #[serde_as(as = "Option<DurationMinutes>")]
+    #[serde(default)]
+    pub seed_time: Option<Duration>,
 
     #[serde(flatten)]
     pub extra_options: Map<String, Value>,
 }
+
+/// Retry options of aria2 download tasks, flattened into [`TaskOptions`].
+///
+/// aria2 has no per-status-code options like `retry-on-403`,
+/// use `max_file_not_found` to retry on "file not found" responses.
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct RetryPolicy {
+    /// Set number of tries. 0 means unlimited.
+    ///
+    /// Default: 5
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub max_tries: Option<u32>,
+
+    /// Set the time to wait between retries.
+    ///
+    /// aria2 takes this value in seconds, so it will be rounded to whole seconds.
+    /// aria2 only retries on 503 responses when this is larger than 0.
+    ///
+    /// Default: 0
+    #[serde_as(as = "Option<DurationSeconds<String>>")]
+    #[serde(default)]
+    pub retry_wait: Option<Duration>,
+
+    /// Fail the download after receiving "file not found" this many times
+    /// without getting a single byte. 0 disables it.
+    ///
+    /// These tries are counted toward `max_tries`, so set it as well.
+    ///
+    /// Only for HTTP/FTP downloads.
+    ///
+    /// Default: 0
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub max_file_not_found: Option<u32>,
+}
+
+/// Options that can be used when adding a download, in alphabetical order.
+///
+/// <https://aria2.github.io/manual/en/html/aria2c.html#id2>
+pub const INPUT_FILE_OPTIONS: &[&str] = &[
+    "all-proxy",
+    "all-proxy-passwd",
+    "all-proxy-user",
+    "allow-overwrite",
+    "allow-piece-length-change",
+    "always-resume",
+    "async-dns",
+    "auto-file-renaming",
+    "bt-enable-hook-after-hash-check",
+    "bt-enable-lpd",
+    "bt-exclude-tracker",
+    "bt-external-ip",
+    "bt-force-encryption",
+    "bt-hash-check-seed",
+    "bt-load-saved-metadata",
+    "bt-max-peers",
+    "bt-metadata-only",
+    "bt-min-crypto-level",
+    "bt-prioritize-piece",
+    "bt-remove-unselected-file",
+    "bt-request-peer-speed-limit",
+    "bt-require-crypto",
+    "bt-save-metadata",
+    "bt-seed-unverified",
+    "bt-stop-timeout",
+    "bt-tracker",
+    "bt-tracker-connect-timeout",
+    "bt-tracker-interval",
+    "bt-tracker-timeout",
+    "check-integrity",
+    "checksum",
+    "conditional-get",
+    "connect-timeout",
+    "content-disposition-default-utf8",
+    "continue",
+    "dir",
+    "dry-run",
+    "enable-http-keep-alive",
+    "enable-http-pipelining",
+    "enable-mmap",
+    "enable-peer-exchange",
+    "file-allocation",
+    "follow-metalink",
+    "follow-torrent",
+    "force-save",
+    "ftp-passwd",
+    "ftp-pasv",
+    "ftp-proxy",
+    "ftp-proxy-passwd",
+    "ftp-proxy-user",
+    "ftp-reuse-connection",
+    "ftp-type",
+    "ftp-user",
+    "gid",
+    "hash-check-only",
+    "header",
+    "http-accept-gzip",
+    "http-auth-challenge",
+    "http-no-cache",
+    "http-passwd",
+    "http-proxy",
+    "http-proxy-passwd",
+    "http-proxy-user",
+    "http-user",
+    "https-proxy",
+    "https-proxy-passwd",
+    "https-proxy-user",
+    "index-out",
+    "lowest-speed-limit",
+    "max-connection-per-server",
+    "max-download-limit",
+    "max-file-not-found",
+    "max-mmap-limit",
+    "max-resume-failure-tries",
+    "max-tries",
+    "max-upload-limit",
+    "metalink-base-uri",
+    "metalink-enable-unique-protocol",
+    "metalink-language",
+    "metalink-location",
+    "metalink-os",
+    "metalink-preferred-protocol",
+    "metalink-version",
+    "min-split-size",
+    "no-file-allocation-limit",
+    "no-netrc",
+    "no-proxy",
+    "out",
+    "parameterized-uri",
+    "pause",
+    "pause-metadata",
+    "piece-length",
+    "proxy-method",
+    "realtime-chunk-checksum",
+    "referer",
+    "remote-time",
+    "remove-control-file",
+    "retry-wait",
+    "reuse-uri",
+    "rpc-save-upload-metadata",
+    "seed-ratio",
+    "seed-time",
+    "select-file",
+    "split",
+    "ssh-host-key-md",
+    "stream-piece-selector",
+    "timeout",
+    "uri-selector",
+    "use-head",
+    "user-agent",
+];
+
+/// Options of [`INPUT_FILE_OPTIONS`] that can't be changed by `aria2.changeOption`.
+///
+/// <https://aria2.github.io/manual/en/html/aria2c.html#aria2.changeOption>
+pub const NOT_CHANGEABLE_OPTIONS: &[&str] = &[
+    "dry-run",
+    "metalink-base-uri",
+    "parameterized-uri",
+    "pause",
+    "piece-length",
+    "rpc-save-upload-metadata",
+];
+
+/// Whether the option can be changed by `aria2.changeOption` after the task is added.
+///
+/// aria2 silently ignores options that can't be changed,
+/// so [`crate::Client::change_option`] returns `Error::OptionNotChangeable` for them instead.
+pub fn is_changeable(key: &str) -> bool {
+    !NOT_CHANGEABLE_OPTIONS.contains(&key)
+}
+
+/// An option in `extra_options` which is not known by aria2.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownOption {
+    pub key: String,
+    /// The closest known option, if the key looks like a typo of it.
+    pub suggestion: Option<&'static str>,
+}
+
+impl TaskOptions {
+    /// Merge `overrides` into a copy of these options.
+    ///
+    /// Options set in `overrides`, including those in `extra_options`, take precedence.
+    pub fn merge(&self, overrides: &TaskOptions) -> Result<TaskOptions, Error> {
+        let mut map = to_map(self)?;
+        map.extend(to_map(overrides)?);
+        serde_json::from_value(Value::Object(map)).context(error::JsonSnafu)
+    }
+
+    /// Check keys of `extra_options` against [`INPUT_FILE_OPTIONS`].
+    ///
+    /// aria2 has no dry-run mode, and options with unknown keys are silently ignored
+    /// or rejected only when adding.
+    /// This helps to catch typos before sending the options.
+    pub fn unknown_options(&self) -> Vec<UnknownOption> {
+        self.extra_options
+            .keys()
+            .filter(|key| INPUT_FILE_OPTIONS.binary_search(&key.as_str()).is_err())
+            .map(|key| UnknownOption {
+                key: key.clone(),
+                suggestion: INPUT_FILE_OPTIONS
+                    .iter()
+                    .map(|option| (edit_distance(key, option), *option))
+                    .filter(|(d, _)| *d <= 2)
+                    .min()
+                    .map(|(_, option)| option),
+            })
+            .collect()
+    }
+}
+
+pub(crate) fn to_map(options: &TaskOptions) -> Result<Map<String, Value>, Error> {
+    match serde_json::to_value(options).context(error::JsonSnafu)? {
+        Value::Object(map) => Ok(map),
+        _ => unreachable!("TaskOptions is serialized as an object"),
+    }
+}
+
+/// Whether setting an option to `value` changes its `current` value returned by aria2.
+///
+/// aria2 returns normalized values, e.g. `1048576` for `1M` and `1.0` for `1`,
+/// so numbers and sizes are compared by their values.
+pub(crate) fn option_changed(current: Option<&str>, value: &Value) -> bool {
+    let (Some(current), Value::String(value)) = (current, value) else {
+        return true;
+    };
+    if current == value {
+        return false;
+    }
+    if let (Ok(a), Ok(b)) = (current.parse::<ByteSize>(), value.parse::<ByteSize>()) {
+        return a != b;
+    }
+    if let (Ok(a), Ok(b)) = (current.parse::<f64>(), value.parse::<f64>()) {
+        return a != b;
+    }
+    true
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                prev
+            } else {
+                1 + prev.min(row[j]).min(cur)
+            };
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
+/// Put all options into `extra_options`.
+impl From<HashMap<String, Value>> for TaskOptions {
+    fn from(map: HashMap<String, Value>) -> Self {
+        Self {
+            extra_options: map.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Put all options into `extra_options`.
+impl From<Map<String, Value>> for TaskOptions {
+    fn from(map: Map<String, Value>) -> Self {
+        Self {
+            extra_options: map,
+            ..Default::default()
+        }
+    }
+}
+
+/// Global options of aria2.
+///
+/// Options of tasks can also be set globally, which are used by tasks added later,
+/// so they are included in `task_options`.
+///
+/// <https://aria2.github.io/manual/en/html/aria2c.html#aria2.changeGlobalOption>
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct GlobalOptions {
+    /// Set the maximum number of parallel downloads for every queue item.
+    ///
+    /// Default: 5
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub max_concurrent_downloads: Option<u32>,
+
+    /// Optimize the number of concurrent downloads according to the bandwidth available.
+    ///
+    /// Default: `OptimizeConcurrentDownloads::Disabled`
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub optimize_concurrent_downloads: Option<OptimizeConcurrentDownloads>,
+
+    /// Set the soft limit of open file descriptors. Linux only.
+    ///
+    /// This option can only be set when aria2 starts.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub rlimit_nofile: Option<u32>,
+
+    /// Set the DSCP value in outgoing IP packets of BitTorrent traffic, from 0 to 63.
+    ///
+    /// This option can only be set when aria2 starts.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub dscp: Option<u8>,
+
+    /// Enable the disk cache, 0 to disable it.
+    ///
+    /// This option can only be set when aria2 starts.
+    ///
+    /// Default: 16M
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub disk_cache: Option<ByteSize>,
+
+    /// Load cookies from this file, in the Firefox3 or Mozilla/Netscape format.
+    ///
+    /// The path is on the host running aria2.
+    /// This option can only be set when aria2 starts.
+    pub load_cookies: Option<PathBuf>,
+
+    /// Save cookies to this file in the Mozilla/Netscape format, including session cookies.
+    ///
+    /// The path is on the host running aria2.
+    /// If the file already exists, it's overwritten.
+    pub save_cookies: Option<PathBuf>,
+
+    /// Options of tasks, and other global options in `extra_options`.
+    #[serde(flatten)]
+    pub task_options: TaskOptions,
+}
+
+/// A size in bytes, used by options taking a size with an optional `K` or `M` suffix.
+///
+/// It's sent to aria2 in bytes, and parsed from both forms (1K = 1024, 1M = 1024K).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    pub fn kib(n: u64) -> Self {
+        Self(n << 10)
+    }
+
+    pub fn mib(n: u64) -> Self {
+        Self(n << 20)
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (n, shift) = match s.as_bytes().last() {
+            Some(b'K' | b'k') => (&s[..s.len() - 1], 10),
+            Some(b'M' | b'm') => (&s[..s.len() - 1], 20),
+            _ => (s, 0),
+        };
+        n.parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(1 << shift))
+            .map(Self)
+            .ok_or_else(|| format!("invalid size: {}", s))
+    }
+}
+
+/// Value of the `optimize-concurrent-downloads` option, `true`, `false` or `A:B`.
+///
+/// aria2 calculates the number of concurrent downloads by
+/// `A + B log10(speed in Mbps)`, where the speed is the highest observed one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptimizeConcurrentDownloads {
+    Disabled,
+    /// Same as `Coefficients { a: 5.0, b: 25.0 }`.
+    Enabled,
+    Coefficients {
+        a: f64,
+        b: f64,
+    },
+}
+
+impl OptimizeConcurrentDownloads {
+    pub fn new(enabled: bool) -> Self {
+        if enabled {
+            Self::Enabled
+        } else {
+            Self::Disabled
+        }
+    }
+
+    pub fn coefficients(a: f64, b: f64) -> Self {
+        Self::Coefficients { a, b }
+    }
+}
+
+impl fmt::Display for OptimizeConcurrentDownloads {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Disabled => f.write_str("false"),
+            Self::Enabled => f.write_str("true"),
+            Self::Coefficients { a, b } => write!(f, "{}:{}", a, b),
+        }
+    }
+}
+
+impl FromStr for OptimizeConcurrentDownloads {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "false" => Ok(Self::Disabled),
+            "true" => Ok(Self::Enabled),
+            _ => {
+                let parse =
+                    |a: &str, b: &str| Some(Self::coefficients(a.parse().ok()?, b.parse().ok()?));
+                s.split_once(':')
+                    .and_then(|(a, b)| parse(a, b))
+                    .ok_or_else(|| format!("invalid optimize-concurrent-downloads: {}", s))
+            }
+        }
+    }
+}
+
+impl From<TaskOptions> for GlobalOptions {
+    fn from(task_options: TaskOptions) -> Self {
+        Self {
+            task_options,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::{
+        options::{
+            is_changeable, option_changed, ByteSize, GlobalOptions, OptimizeConcurrentDownloads,
+            RetryPolicy, UnknownOption,
+        },
+        TaskOptions,
+    };
+
+    #[test]
+    fn seed_options() {
+        let options = TaskOptions {
+            seed_ratio: Some(1.5),
+            seed_time: Some(Duration::from_secs(90)),
+            ..Default::default()
+        };
+        let v = serde_json::to_value(&options).unwrap();
+        assert_eq!(v, json!({"seed-ratio": "1.5", "seed-time": "1.5"}));
+        assert_eq!(serde_json::from_value::<TaskOptions>(v).unwrap(), options);
+    }
+
+    #[test]
+    fn flattened_task_options() {
+        let v = json!({"max-concurrent-downloads": "3", "dir": "/tmp", "log-level": "warn"});
+        let options: GlobalOptions = serde_json::from_value(v.clone()).unwrap();
+        assert_eq!(options.max_concurrent_downloads, Some(3));
+        assert_eq!(options.task_options.dir.as_deref(), Some("/tmp"));
+        assert_eq!(options.task_options.extra_options["log-level"], "warn");
+        assert_eq!(serde_json::to_value(&options).unwrap(), v);
+    }
+
+    #[test]
+    fn retry_policy() {
+        let options = TaskOptions {
+            retry: RetryPolicy {
+                max_tries: Some(10),
+                retry_wait: Some(Duration::from_secs(5)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let v = serde_json::to_value(&options).unwrap();
+        assert_eq!(v, json!({"max-tries": "10", "retry-wait": "5"}));
+        assert_eq!(serde_json::from_value::<TaskOptions>(v).unwrap(), options);
+
+        let v = json!({"max-file-not-found": "2", "user-agent": "aria2"});
+        let options: TaskOptions = serde_json::from_value(v).unwrap();
+        assert_eq!(options.retry.max_file_not_found, Some(2));
+        assert!(!options.extra_options.contains_key("max-file-not-found"));
+    }
+
+    #[test]
+    fn changed_values() {
+        assert!(!option_changed(Some("1048576"), &json!("1M")));
+        assert!(!option_changed(Some("1.0"), &json!("1")));
+        assert!(!option_changed(Some("true"), &json!("true")));
+        assert!(option_changed(Some("5"), &json!("3")));
+        assert!(option_changed(None, &json!("3")));
+    }
+
+    #[test]
+    fn changeable_options() {
+        assert!(is_changeable("max-download-limit"));
+        assert!(!is_changeable("pause"));
+        assert!(!is_changeable("piece-length"));
+    }
+
+    #[test]
+    fn tracker_lists() {
+        let options = TaskOptions {
+            bt_tracker: Some(vec![
+                "udp://tracker.example.com:80/announce".to_string(),
+                "http://tracker.example.org/announce".to_string(),
+            ]),
+            bt_exclude_tracker: Some(vec!["*".to_string()]),
+            ..Default::default()
+        };
+        let v = serde_json::to_value(&options).unwrap();
+        assert_eq!(
+            v,
+            json!({
+                "bt-tracker": "udp://tracker.example.com:80/announce,http://tracker.example.org/announce",
+                "bt-exclude-tracker": "*",
+            })
+        );
+        assert_eq!(serde_json::from_value::<TaskOptions>(v).unwrap(), options);
+    }
+
+    #[test]
+    fn empty_collections() {
+        assert_eq!(
+            serde_json::to_value(TaskOptions::default()).unwrap(),
+            json!({})
+        );
+        let options = TaskOptions {
+            header: Some(vec![]),
+            bt_tracker: Some(vec![]),
+            bt_exclude_tracker: Some(vec![]),
+            extra_options: Default::default(),
+            split: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(options).unwrap(),
+            json!({"split": "4"})
+        );
+    }
+
+    #[test]
+    fn optimize_concurrent_downloads() {
+        for (value, s) in [
+            (OptimizeConcurrentDownloads::new(false), "false"),
+            (OptimizeConcurrentDownloads::new(true), "true"),
+            (
+                OptimizeConcurrentDownloads::coefficients(5.0, 25.5),
+                "5:25.5",
+            ),
+        ] {
+            let options = GlobalOptions {
+                optimize_concurrent_downloads: Some(value),
+                ..Default::default()
+            };
+            let v = serde_json::to_value(&options).unwrap();
+            assert_eq!(v, json!({ "optimize-concurrent-downloads": s }));
+            assert_eq!(serde_json::from_value::<GlobalOptions>(v).unwrap(), options);
+        }
+        assert!("5".parse::<OptimizeConcurrentDownloads>().is_err());
+    }
+
+    #[test]
+    fn advanced_network_options() {
+        let options: GlobalOptions = serde_json::from_value(
+            json!({"rlimit-nofile": "1024", "dscp": "8", "disk-cache": "16M"}),
+        )
+        .unwrap();
+        assert_eq!(options.rlimit_nofile, Some(1024));
+        assert_eq!(options.dscp, Some(8));
+        assert_eq!(options.disk_cache, Some(ByteSize::mib(16)));
+        assert_eq!(
+            serde_json::to_value(&options).unwrap()["disk-cache"],
+            "16777216"
+        );
+        assert_eq!("32k".parse(), Ok(ByteSize::kib(32)));
+        assert!("16G".parse::<ByteSize>().is_err());
+    }
+
+    #[test]
+    fn pause_on_add() {
+        let options = TaskOptions {
+            pause_on_add: Some(true),
+            ..Default::default()
+        };
+        let v = serde_json::to_value(&options).unwrap();
+        assert_eq!(v, json!({"pause": "true"}));
+        assert_eq!(serde_json::from_value::<TaskOptions>(v).unwrap(), options);
+    }
+
+    #[test]
+    fn unknown_options() {
+        let options = TaskOptions {
+            extra_options:
+                json!({"file-allocation": "none", "max-dowload-limit": "1M", "foo": "1"})
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.unknown_options(),
+            [
+                UnknownOption {
+                    key: "foo".to_string(),
+                    suggestion: None
+                },
+                UnknownOption {
+                    key: "max-dowload-limit".to_string(),
+                    suggestion: Some("max-download-limit")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn merge() {
+        let defaults = TaskOptions {
+            dir: Some("/downloads".to_string()),
+            split: Some(4),
+            extra_options: json!({"file-allocation": "none"})
+                .as_object()
+                .unwrap()
+                .clone(),
+            ..Default::default()
+        };
+        let overrides = TaskOptions {
+            split: Some(2),
+            ..Default::default()
+        };
+        let merged = defaults.merge(&overrides).unwrap();
+        assert_eq!(merged.dir.as_deref(), Some("/downloads"));
+        assert_eq!(merged.split, Some(2));
+        assert_eq!(merged.extra_options["file-allocation"], "none");
+    }
+}
@@ -1,35 +1,205 @@
-use chrono::serde::ts_seconds;
+//! Structs of responses and notifications from aria2.
+//!
+//! Fields of response structs are public,
+//! but the structs are `#[non_exhaustive]` so fields can be added as aria2 adds them.
+//! Match them with `..` in struct patterns, e.g. `Status { gid, status, .. }`,
+//! and construct [`Status`] with [`Status::new`].
+
+use std::{
+    borrow::Borrow,
+    fmt,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use chrono::serde::ts_seconds_option;
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 
+use crate::utils::NumberOrString;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
+#[non_exhaustive]
 pub struct Version {
     pub enabled_features: Vec<String>,
 
     pub version: String,
 }
 
+impl Version {
+    /// Parse `version` into a [`SemVer`].
+    pub fn semver(&self) -> SemVer {
+        SemVer::parse(&self.version)
+    }
+}
+
+/// A `major.minor.patch` version number of aria2.
+///
+/// Ordering is done by `major`, `minor`, then `patch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct SemVer {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl SemVer {
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parse a version string like `1.36.0`.
+    ///
+    /// Only the leading digits of each component are used,
+    /// so `1.36.0-dev` is parsed as `1.36.0`.
+    /// Missing or unparseable components are treated as zero.
+    pub fn parse(version: &str) -> Self {
+        let mut parts = version.trim().split('.').map(|part| {
+            let end = part
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(part.len());
+            part[..end].parse().unwrap_or(0)
+        });
+        let mut next = || parts.next().unwrap_or(0);
+        Self::new(next(), next(), next())
+    }
+}
+
+impl std::fmt::Display for SemVer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// GID of a task, normally 16 hexadecimal characters.
+///
+/// Derefs to `str`, so it can be passed to methods taking `&str`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[serde(transparent)]
+pub struct Gid(String);
+
+impl Gid {
+    /// Parse a GID, returning `None` if it's not 16 hexadecimal characters.
+    pub fn parse(gid: &str) -> Option<Self> {
+        if Self::is_valid(gid) {
+            Some(Self(gid.to_ascii_lowercase()))
+        } else {
+            None
+        }
+    }
+
+    fn is_valid(gid: &str) -> bool {
+        gid.len() == 16 && gid.bytes().all(|b| b.is_ascii_hexdigit())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl<'de> Deserialize<'de> for Gid {
+    /// Unexpected formats are kept as is, so a newer aria2 doesn't break parsing.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let gid = String::deserialize(deserializer)?;
+        if !Self::is_valid(&gid) {
+            log::debug!("aria2: unexpected gid format: {}", gid);
+        }
+        Ok(Self(gid))
+    }
+}
+
+impl Deref for Gid {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Gid {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Gid {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Gid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for Gid {
+    fn from(gid: String) -> Self {
+        Self(gid)
+    }
+}
+
+impl From<&str> for Gid {
+    fn from(gid: &str) -> Self {
+        Self(gid.to_string())
+    }
+}
+
+impl From<Gid> for String {
+    fn from(gid: Gid) -> Self {
+        gid.0
+    }
+}
+
+impl PartialEq<str> for Gid {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<String> for Gid {
+    fn eq(&self, other: &String) -> bool {
+        &self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Gid {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
 /// Full status of a task.
 ///
 /// <https://aria2.github.io/manual/en/html/aria2c.html#aria2.tellStatus>
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
+#[non_exhaustive]
 pub struct Status {
     /// GID of the download.
-    pub gid: String,
+    pub gid: Gid,
 
     pub status: TaskStatus,
 
-    #[serde_as(as = "DisplayFromStr")]
+    #[serde_as(as = "NumberOrString")]
     pub total_length: u64,
 
-    #[serde_as(as = "DisplayFromStr")]
+    #[serde_as(as = "NumberOrString")]
     pub completed_length: u64,
 
-    #[serde_as(as = "DisplayFromStr")]
+    #[serde_as(as = "NumberOrString")]
     pub upload_length: u64,
 
     /// Hexadecimal representation of the download progress.
@@ -45,36 +215,57 @@ pub struct Status {
     /// this key will not be included in the response.
     pub bitfield: Option<String>,
 
-    #[serde_as(as = "DisplayFromStr")]
+    #[serde_as(as = "NumberOrString")]
     pub download_speed: u64,
 
-    #[serde_as(as = "DisplayFromStr")]
+    #[serde_as(as = "NumberOrString")]
     pub upload_speed: u64,
 
     /// InfoHash. BitTorrent only
     pub info_hash: Option<String>,
 
-    #[serde_as(as = "Option<DisplayFromStr>")]
+    /// The number of seeders aria2 has connected to. BitTorrent only.
+    #[serde_as(as = "Option<NumberOrString>")]
     #[serde(default)]
-    pub num_seeders: Option<u64>,
+    pub num_seeders: Option<u32>,
 
     /// true if the local endpoint is a seeder. Otherwise false. BitTorrent only.
     #[serde_as(as = "Option<DisplayFromStr>")]
     #[serde(default)]
     pub seeder: Option<bool>,
 
-    #[serde_as(as = "DisplayFromStr")]
-    pub piece_length: u64,
+    /// Piece length in bytes.
+    ///
+    /// `None` if the key is not requested from aria2.
+    #[serde_as(as = "Option<NumberOrString>")]
+    #[serde(default)]
+    pub piece_length: Option<u64>,
 
-    #[serde_as(as = "DisplayFromStr")]
-    pub num_pieces: u64,
+    /// The number of pieces, which is the length of `bitfield` in bits.
+    ///
+    /// `None` if the key is not requested from aria2.
+    #[serde_as(as = "Option<NumberOrString>")]
+    #[serde(default)]
+    pub num_pieces: Option<u32>,
 
-    #[serde_as(as = "DisplayFromStr")]
-    pub connections: u64,
+    /// The number of peers/servers aria2 has connected to.
+    ///
+    /// `0` if the key is not requested from aria2.
+    #[serde_as(as = "NumberOrString")]
+    #[serde(default)]
+    pub connections: u32,
 
+    /// The code of the last error for this item, if any.
+    ///
+    /// The value is a string. The error codes are defined in the EXIT STATUS section.
+    /// This value is only available for stopped/completed downloads.
     pub error_code: Option<String>,
 
+    /// The (hopefully) human readable error message associated to `error_code`.
+    ///
+    /// aria2 may leave it empty. Use [`Status::error_description`] to get a fallback.
     pub error_message: Option<String>,
+
     /// List of GIDs which are generated as the result of this download.
     ///
     /// For example, when aria2 downloads a Metalink file,
@@ -83,12 +274,12 @@ pub struct Status {
     /// This value is useful to track auto-generated downloads.
     ///
     /// If there are no such downloads, this key will not be included in the response.
-    pub followed_by: Option<Vec<String>>,
+    pub followed_by: Option<Vec<Gid>>,
 
     /// The reverse link for followedBy.
     ///
     /// A download included in followedBy has this object's GID in its following value.
-    pub following: Option<String>,
+    pub following: Option<Gid>,
 
     /// GID of a parent download.
     ///
@@ -98,10 +289,15 @@ pub struct Status {
     /// the downloads of ".torrent" files are parts of that parent.
     ///
     /// If this download has no parent, this key will not be included in the response.
-    pub belongs_to: Option<String>,
+    pub belongs_to: Option<Gid>,
 
-    pub dir: String,
+    /// Directory to save files.
+    ///
+    /// This key may be omitted by aria2, e.g. when it's filtered out by keys.
+    pub dir: Option<String>,
 
+    /// Empty if filtered out by keys, see [`crate::Client::tell_status_without_files`].
+    #[serde(default)]
     pub files: Vec<File>,
 
     pub bittorrent: Option<BittorrentStatus>,
@@ -109,7 +305,7 @@ pub struct Status {
     /// The number of verified number of bytes while the files are being hash checked.
     ///
     /// This key exists only when this download is being hash checked.
-    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde_as(as = "Option<NumberOrString>")]
     #[serde(default)]
     pub verified_length: Option<u64>,
 
@@ -121,39 +317,236 @@ pub struct Status {
     pub verify_integrity_pending: Option<bool>,
 }
 
+impl Status {
+    /// Create a status of a task without any progress, files or optional fields.
+    ///
+    /// Useful for constructing statuses in tests, other fields can be set afterwards:
+    ///
+    /// ```
+    /// use aria2_ws::response::{Status, TaskStatus};
+    ///
+    /// let mut status = Status::new("2089b05ecca3d829", TaskStatus::Active);
+    /// status.total_length = 1024;
+    /// status.completed_length = 512;
+    /// assert_eq!(status.gid, "2089b05ecca3d829");
+    /// ```
+    pub fn new(gid: impl Into<Gid>, status: TaskStatus) -> Self {
+        Self {
+            gid: gid.into(),
+            status,
+            total_length: 0,
+            completed_length: 0,
+            upload_length: 0,
+            bitfield: None,
+            download_speed: 0,
+            upload_speed: 0,
+            info_hash: None,
+            num_seeders: None,
+            seeder: None,
+            piece_length: None,
+            num_pieces: None,
+            connections: 0,
+            error_code: None,
+            error_message: None,
+            followed_by: None,
+            following: None,
+            belongs_to: None,
+            dir: None,
+            files: Vec::new(),
+            bittorrent: None,
+            verified_length: None,
+            verify_integrity_pending: None,
+        }
+    }
+
+    /// Directory where the files of this task are saved.
+    ///
+    /// The `path` of each file in `files` is already joined with this directory.
+    pub fn output_dir(&self) -> Option<&Path> {
+        self.dir.as_deref().map(Path::new)
+    }
+
+    /// Describe the error of this task, like `code 3: resource not found`.
+    ///
+    /// If aria2 responds an empty `error_message`,
+    /// the description of `error_code` from [`error_code_description`] is used.
+    ///
+    /// Returns `None` if there is no error.
+    pub fn error_description(&self) -> Option<String> {
+        let code = self.error_code.as_deref().filter(|c| *c != "0")?;
+        let message = self
+            .error_message
+            .as_deref()
+            .filter(|m| !m.is_empty())
+            .or_else(|| error_code_description(code.parse().ok()?))
+            .unwrap_or("unknown error");
+        Some(format!("code {}: {}", code, message))
+    }
+
+    /// Share ratio of the download, `upload_length / completed_length`,
+    /// which is compared with the `seed-ratio` option by aria2.
+    ///
+    /// Returns `None` if nothing is downloaded.
+    pub fn share_ratio(&self) -> Option<f64> {
+        if self.completed_length == 0 {
+            return None;
+        }
+        Some(self.upload_length as f64 / self.completed_length as f64)
+    }
+
+    /// Decode `bitfield` into a list of whether each piece is loaded.
+    ///
+    /// Returns `None` if `bitfield` or `num_pieces` is absent, or `bitfield` is not valid hexadecimal.
+    pub fn pieces(&self) -> Option<Vec<bool>> {
+        let bytes = decode_hex(self.bitfield.as_deref()?)?;
+        Some(
+            (0..self.num_pieces? as usize)
+                .map(|i| bytes.get(i / 8).is_some_and(|b| b & (0x80 >> (i % 8)) != 0))
+                .collect(),
+        )
+    }
+
+    /// Indices of pieces loaded in this status but not in `previous`.
+    pub fn completed_pieces_since(&self, previous: &Status) -> Vec<u32> {
+        if self.bitfield == previous.bitfield {
+            return Vec::new();
+        }
+        let (new, num_pieces) = match (
+            self.bitfield.as_deref().and_then(decode_hex),
+            self.num_pieces,
+        ) {
+            (Some(v), Some(n)) => (v, n),
+            _ => return Vec::new(),
+        };
+        let old = previous
+            .bitfield
+            .as_deref()
+            .and_then(decode_hex)
+            .unwrap_or_default();
+
+        let mut pieces = Vec::new();
+        for (i, b) in new.iter().enumerate() {
+            let diff = b & !old.get(i).copied().unwrap_or(0);
+            for bit in 0..8 {
+                let index = i * 8 + bit;
+                // Bits beyond `num_pieces` are padding.
+                if diff & (0x80 >> bit) != 0 && index < num_pieces as usize {
+                    pieces.push(index as u32);
+                }
+            }
+        }
+        pieces
+    }
+}
+
+/// Description of an aria2 error code.
+///
+/// <https://aria2.github.io/manual/en/html/aria2c.html#exit-status>
+pub fn error_code_description(code: u32) -> Option<&'static str> {
+    Some(match code {
+        0 => "all downloads were successful",
+        1 => "an unknown error occurred",
+        2 => "time out occurred",
+        3 => "a resource was not found",
+        4 => "aria2 saw the specified number of \"resource not found\" error",
+        5 => "a download aborted because download speed was too slow",
+        6 => "network problem occurred",
+        7 => "there were unfinished downloads",
+        8 => "remote server did not support resume when resume was required to complete download",
+        9 => "there was not enough disk space available",
+        10 => "piece length was different from one in .aria2 control file",
+        11 => "aria2 was downloading same file at that moment",
+        12 => "aria2 was downloading same info hash torrent at that moment",
+        13 => "file already existed",
+        14 => "renaming file failed",
+        15 => "aria2 could not open existing file",
+        16 => "aria2 could not create new file or truncate existing file",
+        17 => "file I/O error occurred",
+        18 => "aria2 could not create directory",
+        19 => "name resolution failed",
+        20 => "aria2 could not parse Metalink document",
+        21 => "FTP command failed",
+        22 => "HTTP response header was bad or unexpected",
+        23 => "too many redirects occurred",
+        24 => "HTTP authorization failed",
+        25 => "aria2 could not parse bencoded file",
+        26 => "\".torrent\" file was corrupted or missing information that aria2 needed",
+        27 => "Magnet URI was bad",
+        28 => "bad/unrecognized option was given or unexpected option argument was given",
+        29 => "the remote server was unable to handle the request due to a temporary overloading or maintenance",
+        30 => "aria2 could not parse JSON-RPC request",
+        32 => "checksum validation failed",
+        _ => return None,
+    })
+}
+
+// `usize::is_multiple_of` needs Rust 1.87.
+#[allow(clippy::manual_is_multiple_of)]
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct BittorrentStatus {
-    pub announce_list: Vec<String>,
+    /// List of lists of announce URIs.
+    ///
+    /// If the torrent contains announce and no announce-list,
+    /// announce is converted to the announce-list format.
+    #[serde(default)]
+    pub announce_list: Vec<Vec<String>>,
 
     pub comment: Option<String>,
 
-    #[serde(with = "ts_seconds")]
-    pub creation_date: DateTime<Utc>,
+    #[serde(default, with = "ts_seconds_option")]
+    pub creation_date: Option<DateTime<Utc>>,
 
     pub mode: Option<BitTorrentFileMode>,
+
+    /// Information from the info dictionary.
+    ///
+    /// Not included while the metadata of a magnet link is being downloaded.
+    pub info: Option<BittorrentInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BittorrentInfo {
+    pub name: String,
 }
 
+/// File mode of the torrent.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum BitTorrentFileMode {
     Single,
     Multi,
+    /// Unknown value from a newer aria2.
+    #[serde(other)]
+    Other,
 }
 
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
+#[non_exhaustive]
 pub struct File {
-    #[serde_as(as = "DisplayFromStr")]
-    pub index: u64,
+    /// Index of the file, starting from 1, as used by the `select-file` option.
+    #[serde_as(as = "NumberOrString")]
+    pub index: u32,
 
     pub path: String,
 
-    #[serde_as(as = "DisplayFromStr")]
+    #[serde_as(as = "NumberOrString")]
     pub length: u64,
 
-    #[serde_as(as = "DisplayFromStr")]
+    #[serde_as(as = "NumberOrString")]
     pub completed_length: u64,
 
     #[serde_as(as = "DisplayFromStr")]
@@ -162,8 +555,31 @@ pub struct File {
     pub uris: Vec<Uri>,
 }
 
+impl File {
+    /// Whether this is the placeholder aria2 lists while fetching the metadata of a magnet link,
+    /// with a path like `[METADATA]08ada5a7a6183aae1e09d831df6748d566095a10`.
+    ///
+    /// The real files replace it in the download following this one,
+    /// see `Status::followed_by`.
+    pub fn is_metadata_placeholder(&self) -> bool {
+        self.path.starts_with("[METADATA]")
+    }
+
+    /// The path of the file on the machine running aria2,
+    /// or `None` if it's not known yet, e.g. empty before the name is resolved
+    /// or a metadata placeholder.
+    pub fn path_buf(&self) -> Option<PathBuf> {
+        if self.path.is_empty() || self.is_metadata_placeholder() {
+            None
+        } else {
+            Some(PathBuf::from(&self.path))
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
+#[non_exhaustive]
 pub struct Uri {
     pub status: UriStatus,
 
@@ -175,6 +591,9 @@ pub struct Uri {
 pub enum UriStatus {
     Used,
     Waiting,
+    /// Unknown value from a newer aria2.
+    #[serde(other)]
+    Other,
 }
 
 /// Task status returned by `aria2.tellStatus`.
@@ -201,11 +620,15 @@ pub enum TaskStatus {
     Error,
     Complete,
     Removed,
+    /// Unknown value from a newer aria2.
+    #[serde(other)]
+    Other,
 }
 
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
+#[non_exhaustive]
 pub struct Peer {
     #[serde_as(as = "DisplayFromStr")]
     pub am_choking: bool,
@@ -235,38 +658,99 @@ pub struct Peer {
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
+#[non_exhaustive]
 pub struct GlobalStat {
-    #[serde_as(as = "DisplayFromStr")]
+    #[serde_as(as = "NumberOrString")]
     pub download_speed: u64,
 
-    #[serde_as(as = "DisplayFromStr")]
+    #[serde_as(as = "NumberOrString")]
     pub upload_speed: u64,
 
-    #[serde_as(as = "DisplayFromStr")]
-    pub num_active: i32,
+    #[serde_as(as = "NumberOrString")]
+    pub num_active: u32,
 
-    #[serde_as(as = "DisplayFromStr")]
-    pub num_waiting: i32,
+    #[serde_as(as = "NumberOrString")]
+    pub num_waiting: u32,
 
-    #[serde_as(as = "DisplayFromStr")]
-    pub num_stopped: i32,
+    /// The number of stopped downloads in the current session,
+    /// capped by the `max-download-result` option.
+    ///
+    /// This is the number of results `tell_stopped` can actually return,
+    /// so use it when paging through stopped downloads.
+    #[serde_as(as = "NumberOrString")]
+    pub num_stopped: u32,
 
-    #[serde_as(as = "DisplayFromStr")]
-    pub num_stopped_total: i32,
+    /// The number of stopped downloads in the current session,
+    /// not capped by the `max-download-result` option.
+    ///
+    /// Results beyond `num_stopped` have been discarded by aria2.
+    #[serde_as(as = "NumberOrString")]
+    pub num_stopped_total: u32,
+}
+
+/// Numbers of notifications received in the current connection,
+/// see [`crate::Client::session_stats`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SessionStats {
+    /// Downloads started or resumed.
+    pub started: u64,
+
+    /// Downloads completed, not including BitTorrent downloads that are now seeding.
+    pub completed: u64,
+
+    /// Downloads stopped due to an error.
+    pub errored: u64,
+}
+
+/// Concurrency limit and usage of aria2, see [`crate::Client::concurrency`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConcurrencyInfo {
+    /// The `max-concurrent-downloads` option.
+    pub max: u32,
+
+    /// The number of active downloads.
+    ///
+    /// It may exceed `max` after lowering the limit,
+    /// or by downloads started with `unpause` or `change_position`.
+    pub active: u32,
+
+    /// Free slots for downloads to start without waiting, `max - active` at least 0.
+    pub available: u32,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
+#[non_exhaustive]
 pub struct SessionInfo {
     pub session_id: String,
 }
 
+/// Facts about the aria2 server, see [`crate::Client::server_info`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerInfo {
+    pub version: String,
+
+    pub enabled_features: Vec<String>,
+
+    pub session_id: String,
+}
+
+impl ServerInfo {
+    pub fn semver(&self) -> SemVer {
+        SemVer::parse(&self.version)
+    }
+}
+
+/// Servers connected for a file, returned by [`crate::Client::get_servers`].
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
+#[non_exhaustive]
 pub struct GetServersResult {
-    #[serde_as(as = "DisplayFromStr")]
-    pub index: i32,
+    /// Index of the file, starting from 1, same as [`File::index`].
+    #[serde_as(as = "NumberOrString")]
+    pub index: u32,
 
     pub servers: Vec<Server>,
 }
@@ -274,16 +758,21 @@ pub struct GetServersResult {
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
+#[non_exhaustive]
 pub struct Server {
+    /// The original URI.
     pub uri: String,
 
+    /// The URI currently used, which differs from `uri` if redirected.
     pub current_uri: String,
 
-    #[serde_as(as = "DisplayFromStr")]
+    /// Download speed in bytes/sec.
+    #[serde_as(as = "NumberOrString")]
     pub download_speed: u64,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Copy, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Copy, Hash)]
+#[serde(rename_all = "camelCase")]
 pub enum Event {
     Start,
     Pause,
@@ -294,32 +783,79 @@ pub enum Event {
     BtComplete,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl Event {
+    /// All events, in the order of the aria2 documentation.
+    pub const ALL: [Event; 6] = [
+        Event::Start,
+        Event::Pause,
+        Event::Stop,
+        Event::Complete,
+        Event::Error,
+        Event::BtComplete,
+    ];
+
+    /// The method name of the notification, like `aria2.onDownloadStart`.
+    ///
+    /// <https://aria2.github.io/manual/en/html/aria2c.html#notifications>
+    pub fn method(self) -> &'static str {
+        match self {
+            Event::Start => "aria2.onDownloadStart",
+            Event::Pause => "aria2.onDownloadPause",
+            Event::Stop => "aria2.onDownloadStop",
+            Event::Complete => "aria2.onDownloadComplete",
+            Event::Error => "aria2.onDownloadError",
+            Event::BtComplete => "aria2.onBtDownloadComplete",
+        }
+    }
+
+    /// The event of the notification method name, or `None` if it's unknown.
+    pub fn from_method(method: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|event| event.method() == method)
+    }
+}
+
+/// A notification of a known [`Event`], see [`crate::Client::subscribe_notifications`].
+///
+/// Unknown notifications are only sent to [`crate::Client::subscribe_raw_notifications`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Notification {
-    pub gid: String,
+    pub gid: Gid,
 
     pub event: Event,
 }
 
 impl Notification {
-    pub fn new(gid: String, method: &str) -> Option<Self> {
-        use Event::*;
-        let event = match method {
-            "aria2.onDownloadStart" => Start,
-            "aria2.onDownloadPause" => Pause,
-            "aria2.onDownloadStop" => Stop,
-            "aria2.onDownloadComplete" => Complete,
-            "aria2.onDownloadError" => Error,
-            "aria2.onBtDownloadComplete" => BtComplete,
-            _ => return None,
-        };
-        Some(Self { gid, event })
+    /// Create a notification from the gid and the method name like `aria2.onDownloadStart`.
+    ///
+    /// Returns `None` if the method is not a known notification.
+    pub fn new(gid: impl Into<Gid>, method: &str) -> Option<Self> {
+        Some(Self {
+            gid: gid.into(),
+            event: Event::from_method(method)?,
+        })
     }
 }
 
+/// A transition of a task between two statuses, see [`crate::Client::subscribe_state_changes`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StateChange {
+    pub gid: Gid,
+
+    /// `None` if the task is seen for the first time.
+    pub old: Option<TaskStatus>,
+
+    pub new: TaskStatus,
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::response::{Event, Notification};
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use crate::response::{
+        Event, File, GetServersResult, Gid, GlobalStat, Notification, SemVer, Status, TaskStatus,
+    };
 
     #[test]
     fn event() {
@@ -331,4 +867,240 @@ mod tests {
             Event::BtComplete | Event::Complete | Event::Error
         ));
     }
+
+    #[test]
+    fn event_methods() {
+        for event in Event::ALL {
+            assert_eq!(Event::from_method(event.method()), Some(event));
+        }
+        assert_eq!(Event::Start.method(), "aria2.onDownloadStart");
+        assert_eq!(Event::from_method("aria2.onDownloadStarted"), None);
+    }
+
+    #[test]
+    fn semver() {
+        assert_eq!(SemVer::parse("1.36.0"), SemVer::new(1, 36, 0));
+        assert_eq!(SemVer::parse("1.37.0-dev"), SemVer::new(1, 37, 0));
+        assert_eq!(SemVer::parse("1.x"), SemVer::new(1, 0, 0));
+        assert_eq!(SemVer::parse(""), SemVer::default());
+        assert!(SemVer::parse("1.36.0") > SemVer::new(1, 35, 9));
+    }
+
+    fn test_status(bitfield: &str, num_pieces: u32) -> Status {
+        serde_json::from_value(json!({
+            "gid": "2089b05ecca3d829",
+            "status": "active",
+            "totalLength": "0",
+            "completedLength": "0",
+            "uploadLength": "0",
+            "bitfield": bitfield,
+            "downloadSpeed": "0",
+            "uploadSpeed": "0",
+            "pieceLength": "1048576",
+            "numPieces": num_pieces.to_string(),
+            "connections": "0",
+            "files": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn gid() {
+        assert_eq!(Gid::parse("2089B05ECCA3D829").unwrap(), "2089b05ecca3d829");
+        assert_eq!(Gid::parse("2089b05ecca3d8"), None);
+        assert_eq!(Gid::parse("2089b05ecca3d82x"), None);
+        // Unexpected formats are kept.
+        let gid: Gid = serde_json::from_value(json!("abc")).unwrap();
+        assert_eq!(gid, "abc");
+        assert_eq!(serde_json::to_value(&gid).unwrap(), json!("abc"));
+    }
+
+    #[test]
+    fn number_or_string() {
+        let mut value = serde_json::to_value(test_status("", 0)).unwrap();
+        value["totalLength"] = json!(1024);
+        value["downloadSpeed"] = json!("512");
+        let status: Status = serde_json::from_value(value).unwrap();
+        assert_eq!(status.total_length, 1024);
+        assert_eq!(status.download_speed, 512);
+        // Still serialized as strings like aria2 does.
+        assert_eq!(
+            serde_json::to_value(&status).unwrap()["totalLength"],
+            "1024"
+        );
+
+        let stat: GlobalStat = serde_json::from_value(json!({
+            "downloadSpeed": 100,
+            "uploadSpeed": "0",
+            "numActive": 1,
+            "numWaiting": "2",
+            "numStopped": 3,
+            "numStoppedTotal": "3",
+        }))
+        .unwrap();
+        assert_eq!(stat.download_speed, 100);
+        assert_eq!(stat.num_active, 1);
+        assert_eq!(stat.num_waiting, 2);
+    }
+
+    #[test]
+    fn connections() {
+        let mut value = serde_json::to_value(test_status("", 0)).unwrap();
+        let status: Status = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(status.connections, 0);
+        assert_eq!(status.num_seeders, None);
+
+        value["connections"] = json!("12");
+        value["numSeeders"] = json!("3");
+        let status: Status = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(status.connections, 12);
+        assert_eq!(status.num_seeders, Some(3));
+
+        value.as_object_mut().unwrap().remove("connections");
+        let status: Status = serde_json::from_value(value).unwrap();
+        assert_eq!(status.connections, 0);
+    }
+
+    #[test]
+    fn share_ratio() {
+        let mut status = test_status("", 0);
+        assert_eq!(status.share_ratio(), None);
+        status.completed_length = 200;
+        status.upload_length = 300;
+        assert_eq!(status.share_ratio(), Some(1.5));
+    }
+
+    #[test]
+    fn status_new() {
+        let status = Status {
+            completed_length: 200,
+            upload_length: 300,
+            ..Status::new("2089b05ecca3d829", TaskStatus::Complete)
+        };
+        assert_eq!(status.share_ratio(), Some(1.5));
+        let value = serde_json::to_value(&status).unwrap();
+        assert_eq!(value["status"], "complete");
+        assert_eq!(serde_json::from_value::<Status>(value).unwrap(), status);
+    }
+
+    #[test]
+    fn status_round_trip() {
+        let mut value = serde_json::to_value(test_status("ff80", 10)).unwrap();
+        value["bittorrent"] = json!({
+            "announceList": [["udp://tracker.example.com:80/announce"]],
+            "creationDate": 1600000000,
+            "mode": "multi",
+            "info": {"name": "example"},
+        });
+        value["files"] = json!([{
+            "index": "1",
+            "path": "/downloads/example/a.txt",
+            "length": "1024",
+            "completedLength": "512",
+            "selected": "true",
+            "uris": [{"status": "used", "uri": "http://example.com/a.txt"}],
+        }]);
+        let status: Status = serde_json::from_value(value).unwrap();
+        let value = serde_json::to_value(&status).unwrap();
+        assert_eq!(value["bittorrent"]["creationDate"], 1600000000);
+        assert_eq!(value["files"][0]["length"], "1024");
+        assert_eq!(serde_json::from_value::<Status>(value).unwrap(), status);
+
+        let not = Notification::new("2089b05ecca3d829", "aria2.onBtDownloadComplete").unwrap();
+        let value = serde_json::to_value(&not).unwrap();
+        assert_eq!(
+            value,
+            json!({"gid": "2089b05ecca3d829", "event": "btComplete"})
+        );
+        assert_eq!(serde_json::from_value::<Notification>(value).unwrap(), not);
+    }
+
+    #[test]
+    fn servers() {
+        let result: GetServersResult = serde_json::from_value(json!({
+            "index": "1",
+            "servers": [{
+                "uri": "http://example.com/file",
+                "currentUri": "http://mirror.example.com/file",
+                "downloadSpeed": "10240",
+            }],
+        }))
+        .unwrap();
+        assert_eq!(result.index, 1);
+        assert_eq!(
+            result.servers[0].current_uri,
+            "http://mirror.example.com/file"
+        );
+        assert_eq!(result.servers[0].download_speed, 10240);
+    }
+
+    #[test]
+    fn metadata_placeholder() {
+        let file: File = serde_json::from_value(json!({
+            "index": "1",
+            "path": "[METADATA]08ada5a7a6183aae1e09d831df6748d566095a10",
+            "length": "0",
+            "completedLength": "0",
+            "selected": "true",
+            "uris": [],
+        }))
+        .unwrap();
+        assert!(file.is_metadata_placeholder());
+        assert_eq!(file.path_buf(), None);
+        let file = File {
+            path: "/downloads/file.iso".to_string(),
+            ..file
+        };
+        assert!(!file.is_metadata_placeholder());
+        assert_eq!(file.path_buf().unwrap(), Path::new("/downloads/file.iso"));
+        let file = File {
+            path: String::new(),
+            ..file
+        };
+        assert_eq!(file.path_buf(), None);
+    }
+
+    #[test]
+    fn piece_info() {
+        let mut value = serde_json::to_value(test_status("", 10)).unwrap();
+        let status: Status = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(status.piece_length, Some(1048576));
+        assert_eq!(status.num_pieces, Some(10));
+
+        let map = value.as_object_mut().unwrap();
+        map.remove("pieceLength");
+        map.remove("numPieces");
+        let status: Status = serde_json::from_value(value).unwrap();
+        assert_eq!(status.piece_length, None);
+        assert_eq!(status.pieces(), None);
+    }
+
+    #[test]
+    fn bitfield() {
+        let old = test_status("8000", 10);
+        let new = test_status("a0c0", 10);
+        assert_eq!(
+            new.pieces().unwrap(),
+            [true, false, true, false, false, false, false, false, true, true]
+        );
+        assert_eq!(new.completed_pieces_since(&old), [2, 8, 9]);
+        assert!(new.completed_pieces_since(&new).is_empty());
+    }
+
+    #[test]
+    fn error_description() {
+        let mut status = test_status("", 0);
+        assert_eq!(status.error_description(), None);
+        status.error_code = Some("3".to_string());
+        status.error_message = Some("".to_string());
+        assert_eq!(
+            status.error_description().unwrap(),
+            "code 3: a resource was not found"
+        );
+        status.error_message = Some("Resource not found".to_string());
+        assert_eq!(
+            status.error_description().unwrap(),
+            "code 3: Resource not found"
+        );
+    }
 }
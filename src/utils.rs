@@ -1,5 +1,8 @@
-use serde::Serialize;
+use std::{fmt, marker::PhantomData, str::FromStr, time::Duration};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{to_value, Value};
+use serde_with::{DeserializeAs, SerializeAs};
 use snafu::ResultExt;
 
 use crate::{error, Error};
@@ -8,8 +11,6 @@ pub trait PushExt {
     fn push_some<T: Serialize>(&mut self, t: Option<T>) -> Result<(), Error>;
 
     fn push_else<T: Serialize>(&mut self, t: Option<T>, v: Value) -> Result<(), Error>;
-
-    fn push_value<T: Serialize>(&mut self, t: T) -> Result<(), Error>;
 }
 
 impl PushExt for Vec<Value> {
@@ -28,11 +29,6 @@ impl PushExt for Vec<Value> {
         }
         Ok(())
     }
-
-    fn push_value<T: Serialize>(&mut self, t: T) -> Result<(), Error> {
-        self.push(to_value(t).context(error::JsonSnafu)?);
-        Ok(())
-    }
 }
 
 /// Convert `Value` into `Vec<Value>`
@@ -46,3 +42,185 @@ pub fn value_into_vec(value: Value) -> Vec<Value> {
     }
     panic!("value is not Value::Array");
 }
+
+/// Check that `data` is a bencoded dictionary with an `info` dictionary, like a torrent file.
+///
+/// Only the top-level structure is checked, the info dictionary itself is not validated.
+pub fn looks_like_torrent(data: &[u8]) -> bool {
+    let Some(mut rest) = data.strip_prefix(b"d") else {
+        return false;
+    };
+    while !rest.starts_with(b"e") {
+        let Some((key, after_key)) = bencode_string(rest) else {
+            return false;
+        };
+        if key == b"info" {
+            return after_key.starts_with(b"d") && skip_bencode(after_key).is_some();
+        }
+        let Some(after_value) = skip_bencode(after_key) else {
+            return false;
+        };
+        rest = after_value;
+    }
+    false
+}
+
+/// Split a bencoded string like `4:spam` from the start of `data`.
+fn bencode_string(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let colon = data.iter().position(|&b| b == b':')?;
+    let len: usize = std::str::from_utf8(&data[..colon]).ok()?.parse().ok()?;
+    let rest = &data[colon + 1..];
+    (rest.len() >= len).then(|| rest.split_at(len))
+}
+
+/// Skip a bencoded value at the start of `data`, and return the rest.
+///
+/// Nested lists and dictionaries are tracked by a counter instead of recursion,
+/// so deeply nested input can't overflow the stack.
+fn skip_bencode(data: &[u8]) -> Option<&[u8]> {
+    let mut depth = 0usize;
+    let mut rest = data;
+    loop {
+        rest = match rest.first()? {
+            b'i' => &rest[rest.iter().position(|&b| b == b'e')? + 1..],
+            b'l' | b'd' => {
+                depth += 1;
+                &rest[1..]
+            }
+            b'e' if depth > 0 => {
+                depth -= 1;
+                &rest[1..]
+            }
+            _ => bencode_string(rest)?.1,
+        };
+        if depth == 0 {
+            return Some(rest);
+        }
+    }
+}
+
+/// Convert a count to the `num` param of `tell_waiting` and `tell_stopped`.
+pub fn saturating_i32(n: u32) -> i32 {
+    i32::try_from(n).unwrap_or(i32::MAX)
+}
+
+/// Encode `data` into a buffer allocated once with the exact encoded length,
+/// since torrents and metalinks can be large.
+pub fn encode_base64(data: &[u8]) -> String {
+    let mut buf = String::with_capacity(data.len().div_ceil(3) * 4);
+    base64::encode_config_buf(data, base64::STANDARD, &mut buf);
+    buf
+}
+
+/// Deserialize `null` as the default value.
+pub fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Skip empty lists in options, which may mean differently from omitted ones to aria2.
+pub fn is_none_or_empty<T>(v: &Option<Vec<T>>) -> bool {
+    !matches!(v, Some(v) if !v.is_empty())
+}
+
+/// Serialize `Duration` as a string of fractional minutes, which is used by options like `seed-time`.
+pub struct DurationMinutes;
+
+impl SerializeAs<Duration> for DurationMinutes {
+    fn serialize_as<S>(source: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&(source.as_secs_f64() / 60.0).to_string())
+    }
+}
+
+impl<'de> DeserializeAs<'de, Duration> for DurationMinutes {
+    fn deserialize_as<D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        let minutes: f64 = s.parse().map_err(de::Error::custom)?;
+        Duration::try_from_secs_f64(minutes * 60.0).map_err(de::Error::custom)
+    }
+}
+
+/// Serialize a number as a string like `DisplayFromStr`,
+/// but deserialize it from either a JSON string or a JSON number.
+///
+/// aria2 sends numbers as strings, but this is not guaranteed by all versions.
+pub struct NumberOrString;
+
+impl<T: fmt::Display> SerializeAs<T> for NumberOrString {
+    fn serialize_as<S>(source: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(source)
+    }
+}
+
+impl<'de, T> DeserializeAs<'de, T> for NumberOrString
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    fn deserialize_as<D>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct Visitor<T>(PhantomData<T>);
+
+        impl<T> de::Visitor<'_> for Visitor<T>
+        where
+            T: FromStr,
+            T::Err: fmt::Display,
+        {
+            type Value = T;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a number or a string of number")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+                v.parse().map_err(E::custom)
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<T, E> {
+                self.visit_str(&v.to_string())
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<T, E> {
+                self.visit_str(&v.to_string())
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<T, E> {
+                self.visit_str(&v.to_string())
+            }
+        }
+
+        deserializer.deserialize_any(Visitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::looks_like_torrent;
+
+    #[test]
+    fn bencoded_info_dict() {
+        assert!(looks_like_torrent(
+            b"d8:announce3:url4:infod6:lengthi1e4:name1:aee"
+        ));
+        assert!(!looks_like_torrent(b"d8:announce3:urle"));
+        assert!(!looks_like_torrent(b"d4:info3:abce"));
+        assert!(!looks_like_torrent(b"<html></html>"));
+        // Truncated
+        assert!(!looks_like_torrent(b"d4:infod6:lengthi1e"));
+        assert!(!looks_like_torrent(b"d99:info"));
+    }
+}
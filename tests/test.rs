@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use aria2_ws::{Client, TaskHooks, TaskOptions};
+use aria2_ws::{response::Event, Client, TaskHooks, TaskOptions};
 use futures::FutureExt;
 use serde_json::json;
 use tokio::{spawn, sync::Semaphore};
@@ -13,6 +13,44 @@ async fn drop_test() {
         .unwrap();
 }
 
+#[tokio::test]
+#[ignore]
+async fn subscribe_before_connected() {
+    let client = Client::connect("ws://127.0.0.1:6800/jsonrpc", None)
+        .await
+        .unwrap();
+    let mut not = client.subscribe_notifications();
+    let gid = client
+        .add_uri(
+            vec!["https://go.dev/dl/go1.17.6.windows-amd64.msi".to_string()],
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+    loop {
+        let msg = not.recv().await.unwrap();
+        if msg.gid == gid && msg.event == Event::Start {
+            break;
+        }
+    }
+    client.force_remove(&gid).await.unwrap();
+}
+
+#[tokio::test]
+#[ignore]
+async fn list_methods() {
+    let client = Client::connect("ws://127.0.0.1:6800/jsonrpc", None)
+        .await
+        .unwrap();
+    let methods = client.list_methods().await.unwrap();
+    assert!(methods.iter().any(|m| m == "aria2.addUri"));
+    let notifications = client.list_notifications().await.unwrap();
+    assert!(notifications.iter().any(|n| n == "aria2.onDownloadStart"));
+}
+
 #[tokio::test]
 #[ignore]
 async fn example() {
@@ -40,8 +78,8 @@ async fn example() {
             ],
             Some(options.clone()),
             None,
-            Some(TaskHooks {
-                on_complete: Some({
+            Some(TaskHooks::new(
+                Some({
                     let s = semaphore.clone();
                     async move {
                         s.add_permits(1);
@@ -49,7 +87,7 @@ async fn example() {
                     }
                     .boxed()
                 }),
-                on_error: Some({
+                Some({
                     let s = semaphore.clone();
                     async move {
                         s.add_permits(1);
@@ -57,7 +95,7 @@ async fn example() {
                     }
                     .boxed()
                 }),
-            }),
+            )),
         )
         .await
         .unwrap();
@@ -71,8 +109,8 @@ async fn example() {
             ],
             Some(options.clone()),
             None,
-            Some(TaskHooks {
-                on_complete: Some({
+            Some(TaskHooks::new(
+                Some({
                     let s = semaphore.clone();
                     async move {
                         s.add_permits(1);
@@ -80,7 +118,7 @@ async fn example() {
                     }
                     .boxed()
                 }),
-                on_error: Some({
+                Some({
                     let s = semaphore.clone();
                     async move {
                         s.add_permits(1);
@@ -88,7 +126,7 @@ async fn example() {
                     }
                     .boxed()
                 }),
-            }),
+            )),
         )
         .await
         .unwrap();